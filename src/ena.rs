@@ -1,4 +1,4 @@
-//! ENA client and data shaping utilities used by the `herring` binary.
+//! ENA client and data shaping utilities behind the `herring` binary and library.
 //!
 //! This module provides request construction, basic retry logic, and the
 //! functions that fetch ONT runs either for a rolling window (`first_public`
@@ -10,21 +10,134 @@
 //! - `HERRING_INSECURE_TLS=1` — disable TLS validation (debug only)
 //! - `HERRING_CA_BUNDLE=/path/to/ca.pem` — add custom CA roots
 //! - `HERRING_TIMEOUT_SECS` — request timeout in seconds
+//! - `HERRING_ENA_TOKEN` — credential sent to the ENA Portal API (see [`EnaToken`])
 //!
 //! ## Errors
-//! Functions return [`anyhow::Result`], wrapping transport and decode errors.
+//! Functions return [`Result`] with a typed [`EnaError`], so callers can match on
+//! the failure; it converts into `anyhow::Error` with `?`. [`EnaError::is_network`]
+//! tells transport failures, non-2xx statuses and deadline exhaustion (exit code 2
+//! in the binary) apart from setup and decode errors.
 
-use anyhow::{bail, Context, Result};
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
-use reqwest::{blocking::Client, Certificate, StatusCode};
-use serde::Deserialize;
-use std::{collections::HashSet, env, fs, thread, time::Duration};
+use reqwest::{blocking::{Client, RequestBuilder}, Certificate, StatusCode};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT_ENCODING, AUTHORIZATION, CONTENT_TYPE};
+use chrono::NaiveDate;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{cell::RefCell, collections::{HashMap, HashSet}, env, fs, io::{BufRead, Write}, path::PathBuf, sync::{Arc, Mutex, PoisonError}, thread, time::{Duration, Instant}};
 use log::{debug, info, warn};
 
 const PORTAL_BASE: &str = "https://www.ebi.ac.uk/ena/portal/api";
+/// ENA's default `limit`; an unbounded search returning a multiple of it was likely capped.
+pub const ENA_ROW_CAP: usize = 100_000;
+/// Tries per request (first attempt included) for searches and webhooks.
+const ATTEMPTS: u32 = 5;
+/// Tries, and the per-request timeout cap, for the handshake.
+const HANDSHAKE_ATTEMPTS: u32 = 2;
+const HANDSHAKE_TIMEOUT_SECS: u64 = 10;
+const USER_AGENT: &str = concat!("herring/", env!("CARGO_PKG_VERSION"), " (+https://nanoporetech.com)");
+
+/// Result of the fetch functions in this module.
+pub type Result<T, E = EnaError> = std::result::Result<T, E>;
+
+/// Why a fetch (or webhook POST) failed.
+#[derive(Debug, thiserror::Error)]
+pub enum EnaError {
+    /// The HTTP client could not be built (unreadable CA bundle, TLS backend setup, rejected portal base).
+    #[error("{what}")]
+    Client {
+        /// The step that failed, e.g. `reading CA bundle <path>`.
+        what: String,
+        /// The underlying I/O or TLS error.
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// Connection, TLS or timeout failure that persisted through every retry.
+    #[error("request error: {0}")]
+    Transport(#[from] reqwest::Error),
+    /// A non-success HTTP status after every retry.
+    #[error("{what} failed: {status}")]
+    Http {
+        /// The final status.
+        status: StatusCode,
+        /// The request URL.
+        url: String,
+        /// What the request was for, e.g. `ENA search(read_run) (window ...)`.
+        what: String,
+    },
+    /// The `--deadline-secs` budget ran out.
+    #[error("{0}")]
+    Deadline(String),
+    /// A 2xx answer for `what` that is not JSON (ENA serves HTML error pages under load).
+    #[error("ENA returned {content_type} instead of JSON for {what}, body starts: {snippet}")]
+    NotJson {
+        /// What the request was for.
+        what: String,
+        /// The `Content-Type` ENA sent.
+        content_type: String,
+        /// The start of the body, on one line.
+        snippet: String,
+    },
+    /// A JSON answer for `what` that does not decode as `read_run` (or `analysis`) rows.
+    #[error("decode search json ({what}), body starts: {snippet}")]
+    Decode {
+        /// The search label.
+        what: String,
+        /// The start of the body, on one line.
+        snippet: String,
+        /// The JSON error.
+        #[source]
+        source: serde_json::Error,
+    },
+    /// A webhook body that could not be serialized.
+    #[error("encode webhook JSON")]
+    Encode(#[source] serde_json::Error),
+    /// One date window of a windowed fetch failed; the underlying error is its source.
+    #[error("window {start}..{end}")]
+    WindowFailed {
+        /// First day of the window.
+        start: NaiveDate,
+        /// Last day of the window (inclusive).
+        end: NaiveDate,
+        /// Why the window's search failed.
+        #[source]
+        source: Box<EnaError>,
+    },
+    /// The handshake failed under `require_handshake`.
+    #[error("{what}")]
+    Handshake {
+        /// Where: the request, or the overall handshake hint.
+        what: String,
+        /// Why it failed.
+        #[source]
+        source: Box<EnaError>,
+    },
+}
+
+impl EnaError {
+    /// A failure talking to ENA (worth retrying later), as opposed to bad setup or bad data.
+    pub fn is_network(&self) -> bool {
+        match self {
+            EnaError::Transport(_) | EnaError::Http { .. } | EnaError::Deadline(_) | EnaError::NotJson { .. } => true,
+            EnaError::Client { .. } | EnaError::Decode { .. } | EnaError::Encode(_) => false,
+            EnaError::WindowFailed { source, .. } | EnaError::Handshake { source, .. } => source.is_network(),
+        }
+    }
+
+    /// Tag a search failure with its window; running out of deadline is not the window's fault.
+    fn in_window(self, start: NaiveDate, end: NaiveDate) -> Self {
+        match self {
+            EnaError::Deadline(_) => self,
+            e => EnaError::WindowFailed { start, end, source: Box::new(e) },
+        }
+    }
+
+    fn client(what: impl Into<String>, source: impl std::error::Error + Send + Sync + 'static) -> Self {
+        EnaError::Client { what: what.into(), source: Box::new(source) }
+    }
+}
 
 /// A single ENA `read_run` row returned by the search endpoint.
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct RunRecord {
     /// Run accession (may be absent in some API responses).
     pub run_accession: Option<String>,
@@ -32,32 +145,145 @@ pub struct RunRecord {
     pub study_accession: String,
     /// Sample accession for this run.
     pub sample_accession: Option<String>,
-    /// Base count for the run (string in API; parsed later as `u64`).
+    /// Base count for the run (string in API; parsed later as `u128`).
     pub base_count: Option<String>,
     /// Instrument model (e.g. "PromethION", "GridION", "MinION").
     pub instrument_model: Option<String>,
     /// ENA library strategy field.
     pub library_strategy: Option<String>,
+    /// ENA library source (e.g. "GENOMIC", "TRANSCRIPTOMIC", "METAGENOMIC").
+    pub library_source: Option<String>,
+    /// ENA library selection (e.g. "RANDOM", "PCR", "cDNA").
+    pub library_selection: Option<String>,
     /// Scientific name as reported by ENA.
     pub scientific_name: Option<String>,
     /// First public date (YYYY-MM-DD).
     pub first_public: Option<String>,
     /// Study title (if provided on the run row).
     pub study_title: Option<String>,
+    /// Semicolon-separated FASTQ locations (host/path, no scheme), one per file.
+    pub fastq_ftp: Option<String>,
+    /// Semicolon-separated FASTQ file sizes in bytes, parallel to `fastq_ftp`.
+    pub fastq_bytes: Option<String>,
+    /// Sample collection date as free text (e.g. `2021`, `2021-03`, `2021-03-01/2021-04-15`).
+    pub collection_date: Option<String>,
+    /// Submitting/sequencing center as reported by ENA.
+    pub center_name: Option<String>,
+    /// NCBI taxonomy id of `scientific_name`.
+    pub tax_id: Option<String>,
+    /// Number of reads (only requested by the `full` preset).
+    pub read_count: Option<String>,
+    /// Semicolon-separated FASTQ MD5 checksums, parallel to `fastq_ftp`.
+    pub fastq_md5: Option<String>,
+    /// Free-text sample title; often carries strain/isolate detail `scientific_name` lacks.
+    pub sample_title: Option<String>,
+}
+
+/// A single ENA `analysis` row (assemblies, consensus sequences, ...), counted per study
+/// by `--include-analyses`; the `analysis` result type has its own field set.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AnalysisRecord {
+    /// Analysis accession (ERZ...).
+    pub analysis_accession: Option<String>,
+    /// Study accession this analysis belongs to.
+    pub study_accession: String,
+    /// ENA analysis type (e.g. `SEQUENCE_ASSEMBLY`, `SEQUENCE_CONSENSUS`).
+    pub analysis_type: Option<String>,
+    /// First public date (YYYY-MM-DD).
+    pub first_public: Option<String>,
 }
 
+/// Fields requested for `analysis` searches; mirrors [`AnalysisRecord`].
+const ANALYSIS_FIELDS: &str = "analysis_accession,study_accession,analysis_type,first_public";
+
+/// Which `read_run` fields to request; `--fields-preset`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum FieldsPreset {
+    /// Accessions, release date and base count only.
+    Minimal,
+    /// The default `RUN_FIELDS` list.
+    #[default]
+    Standard,
+    /// `RUN_FIELDS` plus `read_count`.
+    Full,
+}
+
+impl FieldsPreset {
+    /// Comma-separated `fields` parameter for searches.
+    fn fields(self) -> String {
+        match self {
+            FieldsPreset::Minimal => "run_accession,study_accession,first_public,base_count".to_string(),
+            FieldsPreset::Standard => RUN_FIELDS.join(","),
+            FieldsPreset::Full => format!("{},read_count", RUN_FIELDS.join(",")),
+        }
+    }
+}
+
+/// Fields requested for every `read_run` search by default; mirrors [`RunRecord`].
+const RUN_FIELDS: &[&str] = &[
+    "run_accession",
+    "study_accession",
+    "sample_accession",
+    "base_count",
+    "instrument_model",
+    "library_strategy",
+    "library_source",
+    "library_selection",
+    "scientific_name",
+    "first_public",
+    "study_title",
+    "collection_date",
+    "fastq_ftp",
+    "fastq_bytes",
+    "fastq_md5",
+    "center_name",
+    "tax_id",
+    "sample_title",
+];
+
+/// Every label [`map_platform`] can return.
+pub const PLATFORMS: [&str; 6] = ["PromethION P2", "PromethION", "GridION", "Flongle", "MinION", "Oxford Nanopore"];
+
 /// Map raw instrument model → a normalized ONT platform label.
 ///
-/// Returns one of: "PromethION", "GridION", "MinION", or "Oxford Nanopore".
+/// Matching is case-insensitive. Returns one of: "PromethION P2" (e.g.
+/// "PromethION 2 Solo", "P2 Solo"), "PromethION", "GridION", "Flongle",
+/// "MinION", or "Oxford Nanopore".
 pub fn map_platform(model: Option<&str>) -> &'static str {
-    if let Some(m) = model {
-        let m = m.to_ascii_lowercase();
-        if m.contains("prometh") { return "PromethION"; }
-        if m.contains("gridion") { return "GridION"; }
-        if m.contains("minion") || m.contains("flongle") { return "MinION"; }
-        if m.contains("ont") { return "Oxford Nanopore"; }
-    }
-    "Oxford Nanopore"
+    model.and_then(ont_pattern).unwrap_or("Oxford Nanopore")
+}
+
+/// Instrument models known to be Oxford Nanopore: ENA's controlled vocabulary for the
+/// platform plus the device names submitters use in practice.
+pub const ONT_MODELS: [&str; 15] = [
+    "MinION", "MinION Mk1B", "MinION Mk1C", "MinION Mk1D", "MinION Flongle", "Flongle",
+    "GridION", "GridION Mk1", "PromethION", "PromethION 24", "PromethION 48",
+    "PromethION 2", "PromethION 2 Solo", "P2", "P2 Solo",
+];
+
+/// Whether `model` is one of [`ONT_MODELS`], compared case-insensitively with runs of
+/// whitespace collapsed; unlike [`map_platform`] there is no substring matching.
+///
+/// `None` for blank models and ENA's "unspecified" placeholder: they carry no evidence
+/// either way, so they are reported as unknown rather than passed as ONT.
+pub fn is_ont_model(model: &str) -> Option<bool> {
+    let m = model.split_whitespace().collect::<Vec<_>>().join(" ");
+    if m.is_empty() || m.eq_ignore_ascii_case("unspecified") { return None; }
+    Some(ONT_MODELS.iter().any(|k| k.eq_ignore_ascii_case(&m)))
+}
+
+/// The label for the first ONT pattern `model` matches, if any.
+fn ont_pattern(model: &str) -> Option<&'static str> {
+    let m = model.to_ascii_lowercase();
+    // Whole words, so "PromethION 24" stays a PromethION.
+    let words: Vec<&str> = m.split_whitespace().collect();
+    if words.contains(&"p2") || words.windows(2).any(|w| w == ["promethion", "2"]) { return Some("PromethION P2"); }
+    if m.contains("prometh") { return Some("PromethION"); }
+    if m.contains("gridion") { return Some("GridION"); }
+    if m.contains("flongle") { return Some("Flongle"); }
+    if m.contains("minion") { return Some("MinION"); }
+    if m.contains("ont") || m.contains("nanopore") { return Some("Oxford Nanopore"); }
+    None
 }
 
 /// Map ENA `library_strategy` to a coarse sequencing type.
@@ -78,44 +304,607 @@ pub fn map_strategy(s: &str) -> String {
     }
 }
 
+/// Wall-clock budget for a whole fetch (all windows, requests and retries).
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    start: Instant,
+    limit: Duration,
+}
+
+impl Deadline {
+    /// Start a budget of `limit` from now.
+    pub fn starting_now(limit: Duration) -> Self {
+        Deadline { start: Instant::now(), limit }
+    }
+
+    /// Fail if the budget is spent, or would be spent after waiting `upcoming`.
+    fn check(&self, upcoming: Duration, what: &str) -> Result<()> {
+        let elapsed = self.start.elapsed();
+        if elapsed + upcoming > self.limit {
+            return Err(EnaError::Deadline(format!("deadline of {}s exceeded after {:.1}s ({})", self.limit.as_secs(), elapsed.as_secs_f64(), what)));
+        }
+        Ok(())
+    }
+}
+
+/// Request pacing for `--rate-limit`: a bucket of one token refilled every `1 / per_sec`
+/// seconds, so requests are spaced evenly. Clones share the bucket and may be used from
+/// several threads. [`FetchOptions`] itself is not `Sync` (its stats and checkpoint are
+/// `RefCell`s), so parallel fetches each take their own `FetchOptions` with a clone of
+/// one `RateLimit`. The binary sends requests serially (there is no `--jobs`).
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    interval: Duration,
+    next: Arc<Mutex<Option<Instant>>>,
+}
+
+impl RateLimit {
+    /// At most `per_sec` requests per second; `per_sec` must be positive and finite.
+    pub fn per_second(per_sec: f64) -> Self {
+        RateLimit { interval: Duration::from_secs_f64(1.0 / per_sec), next: Arc::new(Mutex::new(None)) }
+    }
+
+    /// Claim the next free slot and return how long to wait for it.
+    fn reserve(&self) -> Duration {
+        let now = Instant::now();
+        self.reserve_at(now) - now
+    }
+
+    /// Claim the next free slot at or after `now` and return it.
+    fn reserve_at(&self, now: Instant) -> Instant {
+        let mut next = self.next.lock().unwrap_or_else(PoisonError::into_inner);
+        let at = next.map_or(now, |n| n.max(now));
+        *next = Some(at + self.interval);
+        at
+    }
+}
+
+/// `--resume` checkpoint: every completed window search, appended to a file as one JSON line
+/// (`{"query", "fields", "runs"}`, runs as ENA returned them), so a killed scan can restart
+/// without repeating the windows it already finished.
+///
+/// Entries are matched on the exact query and field list, so only identical windows are
+/// reused. Replaying them through the usual de-duplication gives the same runs as an
+/// uninterrupted fetch.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    path: PathBuf,
+    done: RefCell<HashMap<(String, String), Vec<RunRecord>>>,
+}
+
+#[derive(Serialize, Deserialize)]
+/// One line of a [`Checkpoint`] file.
+struct CheckpointEntry {
+    query: String,
+    fields: String,
+    runs: Vec<RunRecord>,
+}
+
+impl Checkpoint {
+    /// Load the windows recorded in `path` (none if it does not exist yet). Unreadable lines,
+    /// such as one cut short when the previous scan was killed, are skipped with a warning.
+    pub fn open(path: PathBuf) -> std::io::Result<Self> {
+        let mut done = HashMap::new();
+        match fs::File::open(&path) {
+            Ok(f) => {
+                for (i, line) in std::io::BufReader::new(f).lines().enumerate() {
+                    let line = line?;
+                    if line.trim().is_empty() { continue; }
+                    match serde_json::from_str::<CheckpointEntry>(&line) {
+                        Ok(e) => { done.insert((e.query, e.fields), e.runs); }
+                        Err(e) => warn!("{}:{}: skipping unreadable checkpoint line: {}", path.display(), i + 1, e),
+                    }
+                }
+                info!("checkpoint {}: {} completed window(s) to reuse", path.display(), done.len());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => info!("checkpoint {} starts empty", path.display()),
+            Err(e) => return Err(e),
+        }
+        Ok(Checkpoint { path, done: RefCell::new(done) })
+    }
+
+    /// The runs of an already completed search, if recorded.
+    fn take(&self, query: &str, fields: &str) -> Option<Vec<RunRecord>> {
+        self.done.borrow_mut().remove(&(query.to_string(), fields.to_string()))
+    }
+
+    /// Append a completed search. Failing to write only costs resumability, so it warns.
+    fn record(&self, query: &str, fields: &str, runs: &[RunRecord]) {
+        let entry = CheckpointEntry { query: query.to_string(), fields: fields.to_string(), runs: runs.to_vec() };
+        let write = || -> std::io::Result<()> {
+            let mut f = fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+            let mut line = serde_json::to_vec(&entry)?;
+            line.push(b'\n');
+            f.write_all(&line)?;
+            f.sync_data()
+        };
+        if let Err(e) = write() { warn!("could not update checkpoint {}: {} (this window will be fetched again on resume)", self.path.display(), e); }
+    }
+
+    /// Remove the checkpoint file once its runs made it into the final output.
+    pub fn finish(&self) -> std::io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => { info!("checkpoint {} consolidated and removed", self.path.display()); Ok(()) }
+        }
+    }
+}
+
+/// One search request's contribution to a fetch, for `--stats`.
+#[derive(Debug, Clone)]
+pub struct WindowStat {
+    /// What was queried: a date range or an accession chunk.
+    pub label: String,
+    /// Rows ENA returned.
+    pub runs: usize,
+    /// Rows kept after de-duplicating by run accession.
+    pub new: usize,
+    /// An unpaged request returned a multiple of [`ENA_ROW_CAP`] rows, as a capped result would.
+    pub possibly_truncated: bool,
+}
+
+/// Retries of one kind within one search, for `--stats`.
+#[derive(Debug, Clone)]
+pub struct RetryStat {
+    /// The search being retried (`window 2024-01-01..2024-01-14`, ...), or the request URL outside a search.
+    pub label: String,
+    /// `HTTP <status>` or the transport failure kind (`timeout`, `connect`, ...).
+    pub reason: String,
+    /// Retries with this label and reason.
+    pub count: u32,
+}
+
+/// Per-window counts and retries collected while fetching.
+#[derive(Debug, Clone, Default)]
+pub struct FetchStats {
+    /// One entry per search request, in fetch order.
+    pub windows: Vec<WindowStat>,
+    /// Retry attempts (after a retryable status or transport error), handshake included.
+    pub retries: u32,
+    /// HTTP requests sent, every attempt counted.
+    pub requests: u32,
+    /// Search response body bytes after decompression (reqwest drops `Content-Length`
+    /// for compressed responses, so the body is measured as it is read).
+    pub bytes: u64,
+    /// Windows that failed under `keep_partial`, as `label: error`.
+    pub failed: Vec<String>,
+    /// Each requested release window of [`fetch_runs_in_windows`], totalled over its 14-day searches.
+    pub spans: Vec<WindowStat>,
+    /// Retries tallied by search and reason, in first-seen order.
+    pub retry_reasons: Vec<RetryStat>,
+    /// Label of the search in flight, so retries can be attributed to it.
+    current: Option<String>,
+}
+
+/// Client settings from the command line; each one takes precedence over its
+/// `HERRING_*` environment variable.
+#[derive(Debug, Clone, Default)]
+pub struct ClientConfig {
+    /// Request timeout; beats HERRING_TIMEOUT_SECS.
+    pub timeout_secs: Option<u64>,
+    /// Extra root CA(s) to trust; beats HERRING_CA_BUNDLE.
+    pub ca_bundle: Option<PathBuf>,
+    /// Skip certificate validation; `true` beats HERRING_INSECURE_TLS.
+    pub insecure_tls: bool,
+    /// ENA Portal API base URL; beats HERRING_PORTAL_BASE.
+    pub portal_base: Option<String>,
+    /// Accept an `http://` portal base (with a warning) instead of refusing it.
+    pub allow_insecure_scheme: bool,
+    /// Credential for ENA requests; beats HERRING_ENA_TOKEN.
+    pub ena_token: Option<EnaToken>,
+}
+
+/// A credential for the ENA Portal API (`--ena-token` / `HERRING_ENA_TOKEN`).
+///
+/// `user:password` (e.g. data hub `dcc_...` credentials) is sent as HTTP Basic,
+/// anything else as a `Bearer` token. `Debug` never shows the value, and the header
+/// is marked sensitive.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EnaToken(String);
+
+impl std::fmt::Debug for EnaToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EnaToken(<redacted>)")
+    }
+}
+
+impl std::str::FromStr for EnaToken {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        let s = s.trim();
+        if s.is_empty() { return Err("the ENA token is empty".to_string()); }
+        Ok(EnaToken(s.to_string()))
+    }
+}
+
+impl EnaToken {
+    /// `Basic` for `user:password`, else `Bearer`.
+    fn scheme(&self) -> &'static str {
+        if self.0.contains(':') { "Basic" } else { "Bearer" }
+    }
+
+    /// The `Authorization` header value.
+    fn header(&self) -> Result<HeaderValue> {
+        use base64::Engine;
+        let value = match self.scheme() {
+            "Basic" => format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(&self.0)),
+            _ => format!("Bearer {}", self.0),
+        };
+        let mut v = HeaderValue::from_str(&value).map_err(|e| EnaError::client("ENA token (not a valid header value)", e))?;
+        v.set_sensitive(true);
+        Ok(v)
+    }
+}
+
+/// Run-wide options shared by the fetch functions.
+#[derive(Debug, Clone, Default)]
+pub struct FetchOptions {
+    /// Abort the whole fetch once this budget is exhausted (checked between windows and before retry sleeps).
+    pub deadline: Option<Deadline>,
+    /// HTTP client overrides.
+    pub client: ClientConfig,
+    /// Filled in as requests complete; always collected, printed on `--stats`.
+    pub stats: RefCell<FetchStats>,
+    /// Fail the fetch when the handshake fails, instead of warning and carrying on.
+    pub require_handshake: bool,
+    /// Log, record and skip a failing window instead of aborting the fetch.
+    pub keep_partial: bool,
+    /// Field set requested from ENA.
+    pub preset: FieldsPreset,
+    /// Page through searches with this `limit` and increasing `offset` instead of one `limit=0` request.
+    pub page_size: Option<usize>,
+    /// Space out every request (retries, handshake and webhook included); clone one
+    /// [`RateLimit`] into each thread's options to cap parallel fetches together.
+    pub rate_limit: Option<RateLimit>,
+    /// Reuse and record completed window searches (`--resume`).
+    pub checkpoint: Option<Checkpoint>,
+}
+
+impl FetchOptions {
+    fn record_window(&self, label: String, runs: usize, new: usize) {
+        // Paged searches stop only on a short page, so only the single unbounded request can be cut off.
+        let possibly_truncated = self.page_size.is_none() && runs > 0 && runs % ENA_ROW_CAP == 0;
+        if possibly_truncated {
+            warn!("{}: {} runs returned, a multiple of ENA's {} row limit; possibly truncated (try --page-size)", label, runs, ENA_ROW_CAP);
+        } else {
+            info!("{}: {} runs returned (complete)", label, runs);
+        }
+        self.stats.borrow_mut().windows.push(WindowStat { label, runs, new, possibly_truncated });
+    }
+
+    fn record_retry(&self, url: &str, reason: String) {
+        let mut s = self.stats.borrow_mut();
+        s.retries += 1;
+        let label = s.current.clone().unwrap_or_else(|| url.split('?').next().unwrap_or(url).to_string());
+        match s.retry_reasons.iter_mut().find(|t| t.label == label && t.reason == reason) {
+            Some(t) => t.count += 1,
+            None => s.retry_reasons.push(RetryStat { label, reason, count: 1 }),
+        }
+    }
+
+    /// Log request count and bytes received at info level (`-v`).
+    pub fn log_traffic(&self) {
+        let s = self.stats.borrow();
+        info!("network: {} request(s), {} bytes received ({} retries)", s.requests, s.bytes, s.retries);
+    }
+
+    fn check_deadline(&self, upcoming: Duration, what: &str) -> Result<()> {
+        match &self.deadline {
+            Some(d) => d.check(upcoming, what),
+            None => Ok(()),
+        }
+    }
+
+    /// Wait for a `rate_limit` slot, if any; the wait counts against the deadline.
+    fn pace(&self) -> Result<()> {
+        let Some(limit) = &self.rate_limit else { return Ok(()) };
+        let wait = limit.reserve();
+        if !wait.is_zero() {
+            self.check_deadline(wait, "waiting for --rate-limit")?;
+            debug!("rate limit: waiting {:.0}ms", wait.as_secs_f64() * 1000.0);
+            thread::sleep(wait);
+        }
+        Ok(())
+    }
+}
+
+/// Leniently interpret an ENA `collection_date` as an inclusive date range.
+///
+/// Accepts `YYYY`, `YYYY-MM`, `YYYY-MM-DD` (optionally followed by a `T...` time)
+/// and `A/B` ranges of those forms. Returns `None` for anything else, including
+/// the INSDC "missing"/"not collected" vocabulary.
+pub fn parse_collection_date(s: &str) -> Option<(NaiveDate, NaiveDate)> {
+    fn one(s: &str) -> Option<(NaiveDate, NaiveDate)> {
+        let s = s.trim();
+        let s = s.split('T').next().unwrap_or(s);
+        let parts: Vec<&str> = s.split('-').collect();
+        let year: i32 = parts.first()?.parse().ok()?;
+        match parts.len() {
+            1 => Some((NaiveDate::from_ymd_opt(year, 1, 1)?, NaiveDate::from_ymd_opt(year, 12, 31)?)),
+            2 => {
+                let month: u32 = parts[1].parse().ok()?;
+                let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+                let next = if month == 12 { NaiveDate::from_ymd_opt(year + 1, 1, 1)? } else { NaiveDate::from_ymd_opt(year, month + 1, 1)? };
+                Some((first, next.pred_opt()?))
+            }
+            3 => {
+                let d = NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+                Some((d, d))
+            }
+            _ => None,
+        }
+    }
+    match s.split_once('/') {
+        Some((a, b)) => {
+            let (lo, _) = one(a)?;
+            let (_, hi) = one(b)?;
+            if hi < lo { None } else { Some((lo, hi)) }
+        }
+        None => one(s),
+    }
+}
+
 /// Construct a blocking HTTP client with optional TLS overrides and timeouts.
-fn make_client(ua: &str) -> Result<Client> {
-    let mut builder = Client::builder().user_agent(ua);
-    if env::var("HERRING_INSECURE_TLS").as_deref() == Ok("1") {
+///
+/// Responses are requested with `Accept-Encoding: gzip, deflate, br` and are
+/// transparently decompressed by `reqwest` before JSON decoding.
+fn make_client(ua: &str, cfg: &ClientConfig) -> Result<Client> {
+    check_portal_base(cfg)?;
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip, deflate, br"));
+    if let Some((token, src)) = ena_token(cfg) {
+        if base_of(cfg).starts_with("http:") {
+            return Err(EnaError::Client { what: format!("ENA token from {}", src), source: "refusing to send credentials to a plain http portal base".into() });
+        }
+        headers.insert(AUTHORIZATION, token.header()?);
+        info!("sending ENA token from {} ({} auth)", src, token.scheme());
+    }
+    let mut builder = Client::builder()
+        .user_agent(ua)
+        .default_headers(headers)
+        .gzip(true)
+        .deflate(true)
+        .brotli(true);
+    if let Some(src) = insecure_tls(cfg) {
         builder = builder.danger_accept_invalid_certs(true);
-        warn!("TLS validation disabled via HERRING_INSECURE_TLS=1");
+        warn!("TLS validation disabled via {}", src);
     }
-    if let Ok(p) = env::var("HERRING_CA_BUNDLE") {
-        let pem = fs::read(&p)?;
-        builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
-        info!("added extra root certificate(s) from {}", p);
+    if let Some(p) = ca_bundle(cfg) {
+        let pem = fs::read(&p).map_err(|e| EnaError::client(format!("reading CA bundle {}", p.display()), e))?;
+        builder = builder.add_root_certificate(Certificate::from_pem(&pem).map_err(|e| EnaError::client(format!("parsing CA bundle {}", p.display()), e))?);
+        info!("added extra root certificate(s) from {}", p.display());
     }
-    let timeout = env::var("HERRING_TIMEOUT_SECS").ok().and_then(|v| v.parse::<u64>().ok()).unwrap_or(30);
+    let timeout = timeout_secs(cfg);
     builder = builder.timeout(Duration::from_secs(timeout));
     info!("HTTP client timeout = {}s", timeout);
-    Ok(builder.build()?)
+    builder.build().map_err(|e| EnaError::client("building HTTP client", e))
+}
+
+/// Where insecure TLS was requested from, if anywhere: the flag, then HERRING_INSECURE_TLS=1.
+fn insecure_tls(cfg: &ClientConfig) -> Option<&'static str> {
+    if cfg.insecure_tls { Some("--insecure-tls") }
+    else if env::var("HERRING_INSECURE_TLS").as_deref() == Ok("1") { Some("HERRING_INSECURE_TLS=1") }
+    else { None }
+}
+
+/// Extra CA bundle: the flag, then HERRING_CA_BUNDLE.
+fn ca_bundle(cfg: &ClientConfig) -> Option<PathBuf> {
+    cfg.ca_bundle.clone().or_else(|| env::var_os("HERRING_CA_BUNDLE").map(PathBuf::from))
+}
+
+/// Request timeout: `--timeout-secs`, else `HERRING_TIMEOUT_SECS`, else 30 seconds.
+fn timeout_secs(cfg: &ClientConfig) -> u64 {
+    cfg.timeout_secs
+        .or_else(|| env::var("HERRING_TIMEOUT_SECS").ok().and_then(|v| v.parse::<u64>().ok()))
+        .unwrap_or(30)
+}
+
+/// Portal base in effect: `--portal-base`, then HERRING_PORTAL_BASE, then ENA's; no trailing `/`.
+fn base_of(cfg: &ClientConfig) -> String {
+    let raw = cfg.portal_base.clone().or_else(|| env::var("HERRING_PORTAL_BASE").ok()).unwrap_or_else(|| PORTAL_BASE.to_string());
+    raw.trim().trim_end_matches('/').to_string()
+}
+
+/// Refuse a malformed portal base, or one that is not `https://` unless `allow_insecure_scheme`
+/// (then warn, since every query and response would travel in the clear).
+fn check_portal_base(cfg: &ClientConfig) -> Result<()> {
+    let base = base_of(cfg);
+    let url = url::Url::parse(&base).map_err(|e| EnaError::client(format!("invalid portal base {:?}", base), e))?;
+    let refuse = |why: String| EnaError::Client { what: format!("invalid portal base {:?}", base), source: why.into() };
+    if url.cannot_be_a_base() || url.host_str().map_or(true, str::is_empty) { return Err(refuse("expected an absolute URL such as https://host/ena/portal/api".to_string())); }
+    if url.query().is_some() || url.fragment().is_some() { return Err(refuse("query strings and fragments are not allowed".to_string())); }
+    match url.scheme() {
+        "https" => Ok(()),
+        "http" if cfg.allow_insecure_scheme => {
+            warn!("portal base {} is plain HTTP (--allow-insecure-scheme): ENA queries and responses are sent unencrypted", base);
+            Ok(())
+        }
+        "http" => Err(refuse("plain http is refused; use https, or pass --allow-insecure-scheme to accept the downgrade".to_string())),
+        other => Err(refuse(format!("unsupported scheme {:?}; expected https", other))),
+    }
+}
+
+/// The ENA credential and where it came from: `--ena-token`, then HERRING_ENA_TOKEN (if non-empty).
+fn ena_token(cfg: &ClientConfig) -> Option<(EnaToken, &'static str)> {
+    if let Some(t) = &cfg.ena_token { return Some((t.clone(), "--ena-token")); }
+    env::var("HERRING_ENA_TOKEN").ok().and_then(|v| v.parse().ok()).map(|t| (t, "HERRING_ENA_TOKEN"))
+}
+
+/// Base URL of the ENA Portal API when no flag is given: HERRING_PORTAL_BASE, then ENA's.
+pub fn portal_base() -> String {
+    base_of(&ClientConfig::default())
+}
+
+/// Request timeout when no flag is given: HERRING_TIMEOUT_SECS, then 30.
+pub fn default_timeout_secs() -> u64 {
+    timeout_secs(&ClientConfig::default())
+}
+
+/// Environment variables that change client behavior: ours plus the proxy
+/// variables reqwest picks up. Values are `None` when unset.
+pub fn client_env() -> Vec<(&'static str, Option<String>)> {
+    [
+        "HERRING_INSECURE_TLS", "HERRING_CA_BUNDLE", "HERRING_TIMEOUT_SECS", "HERRING_PORTAL_BASE", "HERRING_ENA_TOKEN",
+        "HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy", "NO_PROXY", "no_proxy",
+    ]
+        .into_iter()
+        // Report only that the token is set, never its value.
+        .map(|k| (k, env::var(k).ok().map(|v| if k == "HERRING_ENA_TOKEN" { "(set; value hidden)".to_string() } else { v })))
+        .collect()
+}
+
+/// Outcome of one `herring doctor` check.
+#[derive(Debug)]
+pub struct Check {
+    /// What was checked (`client`, `results ping`, ...).
+    pub name: &'static str,
+    /// Whether the check passed.
+    pub ok: bool,
+    /// HTTP status, effective settings, or the error chain.
+    pub detail: String,
+}
+
+/// Build the client, then probe the results endpoint and the handshake search,
+/// reporting each step instead of warning and carrying on.
+pub fn doctor(opts: &FetchOptions) -> Vec<Check> {
+    let client = match make_client(USER_AGENT, &opts.client) {
+        Ok(c) => c,
+        Err(e) => return vec![Check { name: "client", ok: false, detail: format!("{:#}", anyhow::Error::new(e)) }],
+    };
+    let tls = insecure_tls(&opts.client).map_or_else(|| "TLS validation on".to_string(), |src| format!("TLS validation OFF ({})", src));
+    let ca = ca_bundle(&opts.client).map_or_else(|| "no extra CA".to_string(), |p| format!("extra CA {}", p.display()));
+    let auth = ena_token(&opts.client).map_or_else(|| "anonymous".to_string(), |(t, src)| format!("{} token from {}", t.scheme(), src));
+    let probe = |name: &'static str, url: &str| match request_with_retries(&client, url, opts) {
+        Ok(r) => Check { name, ok: r.status().is_success(), detail: format!("HTTP {}", r.status()) },
+        // reqwest's message already includes its causes; `{:#}` would repeat them.
+        Err(e) => Check { name, ok: false, detail: e.to_string() },
+    };
+    vec![
+        Check { name: "client", ok: true, detail: format!("{}; {}; timeout {}s; {}; {}; {}", USER_AGENT, base_of(&opts.client), timeout_secs(&opts.client), tls, ca, auth) },
+        probe("results ping", &results_url(&opts.client)),
+        probe("handshake search", &handshake_url(&opts.client)),
+    ]
+}
+
+/// What a `doctor --probe` sample row said about one requested field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldStatus {
+    /// Not in ENA's `read_run` returnable fields, so it was left out of the query.
+    Unknown,
+    /// A returnable field the sample row did not include.
+    Absent,
+    /// Returned, but empty (ENA's null) in the sample row.
+    Empty,
+    /// Returned with this value (shortened).
+    Populated(String),
+}
+
+/// One row of the `doctor --probe` table.
+#[derive(Debug, Clone)]
+pub struct FieldProbe {
+    /// Requested field name.
+    pub field: String,
+    /// What came back.
+    pub status: FieldStatus,
+}
+
+/// `read_run` fields requested under `preset`, plus `extra`, de-duplicated in order.
+pub fn probe_field_list(preset: FieldsPreset, extra: &[String]) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for f in preset.fields().split(',').map(str::to_string).chain(extra.iter().map(|f| f.trim().to_string())) {
+        if !f.is_empty() && !out.contains(&f) { out.push(f); }
+    }
+    out
+}
+
+/// GET `url` and decode its JSON body, labelled `what` in errors.
+fn get_json(client: &Client, url: &str, opts: &FetchOptions, what: &str) -> Result<serde_json::Value> {
+    let r = request_with_retries(client, url, opts)?;
+    if !r.status().is_success() { return Err(EnaError::Http { status: r.status(), url: url.to_string(), what: what.to_string() }); }
+    let body = r.bytes().map_err(EnaError::Transport)?;
+    serde_json::from_slice(&body).map_err(|source| EnaError::Decode { what: what.to_string(), snippet: body_snippet(&body), source })
+}
+
+/// Check `fields` against ENA's `read_run` returnable fields, then fetch one ONT run with
+/// the valid ones and report, per field, whether the row carried a value.
+///
+/// One row only says what that run has: an empty field may be populated elsewhere.
+pub fn probe_fields(fields: &[String], opts: &FetchOptions) -> Result<Vec<FieldProbe>> {
+    let client = make_client(USER_AGENT, &opts.client)?;
+    let listing = get_json(&client, &format!("{}/returnFields?dataPortal=ena&result=read_run&format=json", base_of(&opts.client)), opts, "ENA returnFields(read_run)")?;
+    let known: HashSet<&str> = listing.as_array().into_iter().flatten().filter_map(|f| f.get("columnId")?.as_str()).collect();
+    debug!("{} returnable read_run fields", known.len());
+    let valid: Vec<&str> = fields.iter().map(String::as_str).filter(|f| known.contains(f)).collect();
+    let row = if valid.is_empty() { None } else {
+        let url = build_page_url(&opts.client, "read_run", r#"instrument_platform="OXFORD_NANOPORE""#, &valid.join(","), 1, 0);
+        get_json(&client, &url, opts, "ENA search(read_run) (probe)")?.as_array().and_then(|rows| rows.first().cloned())
+    };
+    Ok(fields.iter().map(|f| {
+        let status = if !known.contains(f.as_str()) { FieldStatus::Unknown } else {
+            match row.as_ref().and_then(|r| r.get(f)) {
+                None => FieldStatus::Absent,
+                Some(serde_json::Value::Null) => FieldStatus::Empty,
+                Some(serde_json::Value::String(v)) if v.is_empty() => FieldStatus::Empty,
+                Some(serde_json::Value::String(v)) => FieldStatus::Populated(v.chars().take(40).collect()),
+                Some(v) => FieldStatus::Populated(v.to_string().chars().take(40).collect()),
+            }
+        };
+        FieldProbe { field: f.clone(), status }
+    }).collect())
 }
 
 /// Send a GET with basic **exponential backoff** on common retryable statuses.
 ///
 /// Retryable: `429, 500, 502, 503, 504`. Non-retryable statuses return immediately.
-fn request_with_retries(client: &Client, url: &str) -> Result<reqwest::blocking::Response> {
+fn request_with_retries(client: &Client, url: &str, opts: &FetchOptions) -> Result<reqwest::blocking::Response> {
+    send_with_retries("GET", url, || client.get(url), ATTEMPTS, opts)
+}
+
+/// Connect errors that another attempt cannot fix: a name that does not resolve, or a
+/// certificate rustls rejected (unless `--insecure-tls`/HERRING_INSECURE_TLS is on).
+/// Timeouts, refusals and resets stay retryable. reqwest only exposes `is_connect`,
+/// so the cause is read off the source chain.
+fn fatal_transport(e: &reqwest::Error, opts: &FetchOptions) -> Option<&'static str> {
+    if e.is_timeout() || !e.is_connect() { return None; }
+    let causes: Vec<String> = std::iter::successors(std::error::Error::source(e), |s| s.source()).map(|s| s.to_string().to_lowercase()).collect();
+    if causes.iter().any(|c| c.contains("dns error") || c.contains("failed to lookup address")) { return Some("DNS lookup failed"); }
+    if insecure_tls(&opts.client).is_none() && causes.iter().any(|c| c.contains("certificate")) { return Some("TLS certificate rejected"); }
+    None
+}
+
+/// Coarse kind of a retryable transport error, for the `--stats` retry tally.
+fn transport_kind(e: &reqwest::Error) -> &'static str {
+    if e.is_timeout() { "timeout" }
+    else if e.is_connect() { "connect" }
+    else if e.is_body() || e.is_decode() { "body" }
+    else { "request" }
+}
+
+/// Retry loop shared by GET and POST; `build` makes a fresh request per attempt.
+fn send_with_retries(method: &str, url: &str, build: impl Fn() -> RequestBuilder, attempts: u32, opts: &FetchOptions) -> Result<reqwest::blocking::Response> {
     let mut delay = Duration::from_millis(400);
-    for attempt in 0..5 {
-        info!("GET {} (attempt {} of 5)", url, attempt + 1);
-        let resp = client.get(url).send();
+    for attempt in 0..attempts {
+        opts.pace()?;
+        info!("{} {} (attempt {} of {})", method, url, attempt + 1, attempts);
+        opts.stats.borrow_mut().requests += 1;
+        let resp = build().send();
         match resp {
             Ok(r) if r.status().is_success() => {
                 info!("<- {}", r.status());
                 debug!("<- headers: {:?}", r.headers());
                 return Ok(r)
             },
-            Ok(r) if matches!(r.status(), StatusCode::TOO_MANY_REQUESTS | StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT | StatusCode::INTERNAL_SERVER_ERROR) => {
+            Ok(r) if matches!(r.status(), StatusCode::TOO_MANY_REQUESTS | StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT | StatusCode::INTERNAL_SERVER_ERROR) => {
                 warn!("<- {} (retryable)", r.status());
-                if attempt == 4 { return Ok(r); }
+                if attempt + 1 == attempts { return Ok(r); }
+                opts.record_retry(url, format!("HTTP {}", r.status().as_u16()));
                 if let Some(retry_after) = r.headers().get(reqwest::header::RETRY_AFTER).and_then(|h| h.to_str().ok()).and_then(|s| s.parse::<u64>().ok()) {
+                    opts.check_deadline(Duration::from_secs(retry_after), "before retry")?;
                     thread::sleep(Duration::from_secs(retry_after));
                 } else {
+                    opts.check_deadline(delay, "before retry")?;
                     thread::sleep(delay);
                     delay *= 2;
                 }
@@ -126,8 +915,14 @@ fn request_with_retries(client: &Client, url: &str) -> Result<reqwest::blocking:
                 return Ok(r);
             }
             Err(e) => {
+                if let Some(why) = fatal_transport(&e, opts) {
+                    warn!("transport error: {} ({}; not retrying)", e, why);
+                    return Err(EnaError::Transport(e))
+                }
                 warn!("transport error: {}", e);
-                if attempt == 4 { return Err(e).context("request error") }
+                if attempt + 1 == attempts { return Err(EnaError::Transport(e)) }
+                opts.record_retry(url, transport_kind(&e).to_string());
+                opts.check_deadline(delay, "before retry")?;
                 thread::sleep(delay);
                 delay *= 2;
             }
@@ -136,146 +931,542 @@ fn request_with_retries(client: &Client, url: &str) -> Result<reqwest::blocking:
     unreachable!();
 }
 
-/// Build the ENA search URL for an arbitrary query + field list.
-fn build_url(query: &str, fields: &str) -> String {
+/// Build the ENA search URL for an arbitrary result type, query and field list (unbounded, `limit=0`).
+fn build_url(cfg: &ClientConfig, result: &str, query: &str, fields: &str) -> String {
+    build_page_url(cfg, result, query, fields, 0, 0)
+}
+
+/// Build one page of an ENA search; `limit=0` asks for everything, `offset=0` is omitted.
+fn build_page_url(cfg: &ClientConfig, result: &str, query: &str, fields: &str, limit: usize, offset: usize) -> String {
     let enc_query = utf8_percent_encode(query, NON_ALPHANUMERIC).to_string();
-    let url = format!(
-        "{base}/search?result=read_run&dataPortal=ena&query={query}&fields={fields}&format=json&limit=0",
-        base = PORTAL_BASE,
+    let mut url = format!(
+        "{base}/search?result={result}&dataPortal=ena&query={query}&fields={fields}&format=json&limit={limit}",
+        base = base_of(cfg),
+        result = result,
         query = enc_query,
-        fields = fields
+        fields = fields,
+        limit = limit
     );
+    if offset > 0 { url.push_str(&format!("&offset={}", offset)); }
     debug!("built URL: {}", url);
     url
 }
 
+/// First 200 chars of a response body on one line, for error messages.
+fn body_snippet(body: &[u8]) -> String {
+    let text = String::from_utf8_lossy(body);
+    let flat = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if flat.is_empty() { return "(empty)".to_string(); }
+    let mut out: String = flat.chars().take(200).collect();
+    if out.len() < flat.len() { out.push('…'); }
+    out
+}
+
+/// Run one `read_run` search for `query`, labelled `what` in errors.
+///
+/// Without `opts.page_size` this is a single unbounded request. With it, pages of
+/// that size are fetched at increasing offsets until a short page comes back, so a
+/// server-side cap cannot silently truncate the result. Non-2xx statuses surface as
+/// [`EnaError::Http`], a 2xx with a non-JSON `Content-Type` as [`EnaError::NotJson`].
+fn search_runs(client: &Client, query: &str, fields: &str, opts: &FetchOptions, what: &str) -> Result<Vec<RunRecord>> {
+    opts.stats.borrow_mut().current = Some(what.to_string());
+    let out = search_pages(client, "read_run", query, fields, opts, what);
+    opts.stats.borrow_mut().current = None;
+    out
+}
+
+/// Page through one search of `result` rows, decoded as `T`.
+fn search_pages<T: DeserializeOwned>(client: &Client, result: &str, query: &str, fields: &str, opts: &FetchOptions, what: &str) -> Result<Vec<T>> {
+    let fetch = |url: &str| -> Result<Vec<T>> {
+        let r = request_with_retries(client, url, opts)?;
+        if !r.status().is_success() { return Err(EnaError::Http { status: r.status(), url: url.to_string(), what: format!("ENA search({}) ({})", result, what) }); }
+        let content_type = r.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let body = r.bytes().map_err(EnaError::Transport)?;
+        opts.stats.borrow_mut().bytes += body.len() as u64;
+        if let Some(ct) = content_type.filter(|ct| !ct.contains("json")) {
+            return Err(EnaError::NotJson { what: format!("ENA search({}) ({})", result, what), content_type: ct, snippet: body_snippet(&body) });
+        }
+        serde_json::from_slice(&body).map_err(|source| EnaError::Decode { what: what.to_string(), snippet: body_snippet(&body), source })
+    };
+    let Some(size) = opts.page_size else { return fetch(&build_url(&opts.client, result, query, fields)) };
+    let mut out: Vec<T> = Vec::new();
+    loop {
+        let page = fetch(&build_page_url(&opts.client, result, query, fields, size, out.len()))?;
+        let n = page.len();
+        out.extend(page);
+        debug!("{}: page of {} at offset {} ({} so far)", what, n, out.len() - n, out.len());
+        if n < size { break; }
+        opts.check_deadline(Duration::ZERO, "between pages")?;
+    }
+    Ok(out)
+}
+
+/// ENA endpoint listing the available result types; cheap to fetch.
+fn results_url(cfg: &ClientConfig) -> String {
+    format!("{}/results?dataPortal=ena", base_of(cfg))
+}
+
+/// One-record ONT search used by the handshake.
+fn handshake_url(cfg: &ClientConfig) -> String {
+    let raw_q: &str = r#"instrument_platform="OXFORD_NANOPORE""#;
+    debug!("handshake raw_query: {}", raw_q);
+    build_page_url(cfg, "read_run", raw_q, "run_accession", 1, 0)
+}
+
 /// Lightweight health check of ENA endpoints used by this client.
-fn ping_results(client: &Client) -> Result<()> {
-    let url = format!("{}/results?dataPortal=ena", PORTAL_BASE);
-    let r = request_with_retries(client, &url)?;
-    if r.status().is_success() { Ok(()) } else { bail!("results ping failed: {}", r.status()) }
+fn ping_results(client: &Client, attempts: u32, opts: &FetchOptions) -> Result<()> {
+    let url = results_url(&opts.client);
+    let r = send_with_retries("GET", &url, || client.get(&url), attempts, opts)?;
+    if r.status().is_success() { Ok(()) } else { Err(EnaError::Http { status: r.status(), url, what: "results ping".into() }) }
 }
 
 /// Perform a minimal handshake to surface early connectivity / rate limit issues.
-fn handshake(client: &Client) -> Result<()> {
-    if let Err(e) = ping_results(client) {
+fn handshake(client: &Client, opts: &FetchOptions) -> Result<()> {
+    if let Err(e) = ping_results(client, HANDSHAKE_ATTEMPTS, opts) {
+        if opts.require_handshake { return Err(EnaError::Handshake { what: format!("GET {}", results_url(&opts.client)), source: Box::new(e) }); }
         warn!("ENA results ping failed: {}", e);
     }
-    let raw_q: &str = r#"instrument_platform="OXFORD_NANOPORE""#;
-    debug!("handshake raw_query: {}", raw_q);
-    let url2 = build_url(raw_q, "run_accession").replace("limit=0", "limit=1");
-    let r2 = request_with_retries(client, &url2)?;
+    let url = handshake_url(&opts.client);
+    let r2 = send_with_retries("GET", &url, || client.get(&url), HANDSHAKE_ATTEMPTS, opts).map_err(|e| EnaError::Handshake { what: format!("GET {}", url), source: Box::new(e) })?;
     if !r2.status().is_success() {
+        if opts.require_handshake {
+            let status = EnaError::Http { status: r2.status(), url: url.clone(), what: "handshake minimal search".into() };
+            return Err(EnaError::Handshake { what: format!("GET {}", url), source: Box::new(status) });
+        }
         warn!("handshake minimal search failed: {}", r2.status());
     }
     Ok(())
 }
 
-/// Fetch runs within a **rolling** window: `first_public >= since` **OR** `last_updated >= since`.
-pub fn fetch_runs_since(since: chrono::NaiveDate) -> Result<Vec<RunRecord>> {
-    let ua = "herring/0.2.1 (+https://nanoporetech.com)";
-    let client = make_client(ua)?;
+/// Run the handshake on its own short-timeout client with [`HANDSHAKE_ATTEMPTS`] tries,
+/// so a flaky start neither stalls nor spends the main retry budget. Failures only warn
+/// unless `opts.require_handshake`.
+fn preflight(opts: &FetchOptions) -> Result<()> {
+    let timeout = timeout_secs(&opts.client).min(HANDSHAKE_TIMEOUT_SECS);
+    let client = make_client(USER_AGENT, &ClientConfig { timeout_secs: Some(timeout), ..opts.client.clone() })?;
+    match handshake(&client, opts) {
+        Err(e) if opts.require_handshake => Err(EnaError::Handshake {
+            what: "ENA handshake failed (--require-handshake); run `herring doctor` to diagnose, or drop the flag to continue past a failed handshake".into(),
+            source: Box::new(e),
+        }),
+        Err(e) => { warn!("ENA handshake warning: {:#}", anyhow::Error::new(e)); Ok(()) }
+        Ok(()) => Ok(()),
+    }
+}
+
+/// POST `body` as JSON to `url` (e.g. a chat webhook) with the usual client and retries.
+///
+/// Returns the final HTTP status; callers decide whether non-2xx is fatal.
+pub fn post_json<T: Serialize + ?Sized>(url: &str, body: &T, opts: &FetchOptions) -> Result<StatusCode> {
+    // The ENA token is for ENA only; never hand it to the webhook.
+    let client = make_client(USER_AGENT, &ClientConfig { ena_token: None, ..opts.client.clone() })?;
+    let payload = serde_json::to_vec(body).map_err(EnaError::Encode)?;
+    let r = send_with_retries("POST", url, || client.post(url).header(CONTENT_TYPE, "application/json").body(payload.clone()), ATTEMPTS, opts)?;
+    Ok(r.status())
+}
 
-    if let Err(e) = handshake(&client) {
-        warn!("ENA handshake warning: {}", e);
+/// [`search_runs`] for one window or chunk. Under `opts.keep_partial` a failure (other
+/// than the deadline running out) is logged, recorded in the stats and yields no runs.
+///
+/// With `opts.checkpoint`, a search recorded there is answered from it, and a successful
+/// one is appended to it; failed windows are never recorded, so a resume retries them.
+fn window_search(client: &Client, query: &str, fields: &str, opts: &FetchOptions, what: &str) -> Result<Vec<RunRecord>> {
+    if let Some(runs) = opts.checkpoint.as_ref().and_then(|cp| cp.take(query, fields)) {
+        info!("{}: {} runs from checkpoint", what, runs.len());
+        return Ok(runs);
     }
+    let result = search_runs(client, query, fields, opts, what);
+    if let (Ok(runs), Some(cp)) = (&result, &opts.checkpoint) { cp.record(query, fields, runs); }
+    match result {
+        Err(e) if opts.keep_partial && !matches!(e, EnaError::Deadline(_)) => {
+            let e = anyhow::Error::new(e);
+            warn!("{} failed, continuing (--keep-partial): {:#}", what, e);
+            opts.stats.borrow_mut().failed.push(format!("{}: {}", what, e));
+            Ok(Vec::new())
+        }
+        other => other,
+    }
+}
+
+/// Fetch runs within a **rolling** window: `first_public` **or** `last_updated` in `[since, today]`.
+///
+/// `today` is the caller's clock reading (normally the current UTC date, read once per run),
+/// so the full-window query and the 14-day fallback agree even across midnight UTC.
+///
+/// With `changed_only`, only runs updated in the window that were already public
+/// before it (`last_updated >= since AND first_public < since`): re-releases and
+/// metadata updates, without newly released studies.
+pub fn fetch_runs_since(since: chrono::NaiveDate, today: chrono::NaiveDate, changed_only: bool, opts: &FetchOptions) -> Result<Vec<RunRecord>> {
+    let client = make_client(USER_AGENT, &opts.client)?;
 
-    let fields = [
-        "run_accession",
-        "study_accession",
-        "sample_accession",
-        "base_count",
-        "instrument_model",
-        "library_strategy",
-        "scientific_name",
-        "first_public",
-        "study_title",
-    ].join(",");
+    preflight(opts)?;
 
-    let q_full = format!(
-        r#"instrument_platform="OXFORD_NANOPORE" AND (first_public>={d} OR last_updated>={d})"#,
-        d = since.format("%Y-%m-%d")
-    );
+    let fields = opts.preset.fields();
+
+    let (d, t) = (since.format("%Y-%m-%d"), today.format("%Y-%m-%d"));
+    let q_full = if changed_only {
+        format!(r#"instrument_platform="OXFORD_NANOPORE" AND last_updated>={d} AND last_updated<={t} AND first_public<{d}"#, d = d, t = t)
+    } else {
+        format!(r#"instrument_platform="OXFORD_NANOPORE" AND ((first_public>={d} AND first_public<={t}) OR (last_updated>={d} AND last_updated<={t}))"#, d = d, t = t)
+    };
     debug!("full-window raw_query: {}", q_full);
-    let url_full = build_url(&q_full, &fields);
-    let resp = request_with_retries(&client, &url_full)?;
-    if resp.status().is_success() {
-        let runs: Vec<RunRecord> = resp.json().context("decode read_run json")?;
-        info!("fetched {} runs in full-window request", runs.len());
-        return Ok(runs);
+    match search_runs(&client, &q_full, &fields, opts, "full window") {
+        Ok(runs) => {
+            let (returned, mut out) = (runs.len(), Vec::new());
+            push_new_runs(runs, &mut HashSet::new(), &mut out);
+            if out.len() < returned { info!("full-window request repeated {} run accession(s); kept the first of each", returned - out.len()); }
+            info!("fetched {} runs in full-window request", out.len());
+            opts.record_window(format!("{}..{} (full)", since, today), returned, out.len());
+            return Ok(out);
+        }
+        // A refused (or error-paged) full-window query falls back to 14-day windows; anything
+        // else is fatal unless --keep-partial, where the windows get their own chance.
+        Err(e) if opts.keep_partial || matches!(e, EnaError::Http { .. } | EnaError::NotJson { .. }) => warn!("{:#}; falling back to 14-day windows", anyhow::Error::new(e)),
+        Err(e) => return Err(e),
     }
 
-    let today = chrono::Utc::now().date_naive();
     let mut dedup: HashSet<String> = HashSet::new();
     let mut out: Vec<RunRecord> = Vec::new();
 
     let mut start = since;
     while start <= today {
+        opts.check_deadline(Duration::ZERO, "between windows")?;
         let end = std::cmp::min(start + chrono::Duration::days(13), today);
-        let q = format!(
-            r#"instrument_platform="OXFORD_NANOPORE" AND ((first_public>={s} AND first_public<={e}) OR (last_updated>={s} AND last_updated<={e}))"#,
-            s = start.format("%Y-%m-%d"),
-            e = end.format("%Y-%m-%d")
-        );
+        let (s, e) = (start.format("%Y-%m-%d"), end.format("%Y-%m-%d"));
+        let q = if changed_only {
+            format!(r#"instrument_platform="OXFORD_NANOPORE" AND last_updated>={s} AND last_updated<={e} AND first_public<{d}"#, s = s, e = e, d = d)
+        } else {
+            format!(r#"instrument_platform="OXFORD_NANOPORE" AND ((first_public>={s} AND first_public<={e}) OR (last_updated>={s} AND last_updated<={e}))"#, s = s, e = e)
+        };
         debug!("window raw_query: {}", q);
-        let url = build_url(&q, &fields);
-        let r = request_with_retries(&client, &url)?;
-        if !r.status().is_success() { bail!("ENA search(read_run) failed: {} (window {}..{})", r.status(), start, end); }
-        let mut runs: Vec<RunRecord> = r.json().context("decode read_run json (windowed)")?;
+        let runs = window_search(&client, &q, &fields, opts, &format!("window {}..{}", start, end)).map_err(|e| e.in_window(start, end))?;
+        let returned = runs.len();
         let before = out.len();
-        for rec in runs.drain(..) {
-            if let Some(acc) = rec.run_accession.as_ref() {
-                if dedup.insert(acc.clone()) { out.push(rec); }
-            } else {
-                out.push(rec);
-            }
-        }
+        push_new_runs(runs, &mut dedup, &mut out);
         info!("window {}..{} -> {} new runs ({} total)", start, end, out.len() - before, out.len());
+        opts.record_window(format!("{}..{}", start, end), returned, out.len() - before);
         start = end + chrono::Duration::days(1);
     }
 
     Ok(out)
 }
 
-/// Fetch runs within a **fixed release** window: `first_public ∈ [start, end]`.
-pub fn fetch_runs_between(start: chrono::NaiveDate, end: chrono::NaiveDate) -> Result<Vec<RunRecord>> {
-    let ua = "herring/0.2.1 (+https://nanoporetech.com)";
-    let client = make_client(ua)?;
-    if let Err(e) = handshake(&client) {
-        warn!("ENA handshake warning: {}", e);
+/// Append the runs whose `run_accession` is not yet in `seen` (runs without one are always kept),
+/// so the full-window, windowed and accession fetches all collapse repeated runs the same way.
+fn push_new_runs(runs: Vec<RunRecord>, seen: &mut HashSet<String>, out: &mut Vec<RunRecord>) {
+    for rec in runs {
+        match rec.run_accession.as_ref() {
+            Some(acc) if !seen.insert(acc.clone()) => {}
+            _ => out.push(rec),
+        }
     }
+}
+
+/// Fetch runs for a verbatim ENA `query` (no platform clause added), for debugging
+/// query clauses. Goes through the same URL building, retries and paging.
+pub fn fetch_runs_raw(query: &str, opts: &FetchOptions) -> Result<Vec<RunRecord>> {
+    let client = make_client(USER_AGENT, &opts.client)?;
+    preflight(opts)?;
+    debug!("raw query: {}", query);
+    let runs = search_runs(&client, query, &opts.preset.fields(), opts, "raw query")?;
+    opts.record_window("raw query".to_string(), runs.len(), runs.len());
+    info!("raw query -> {} runs", runs.len());
+    Ok(runs)
+}
 
-    let fields = [
-        "run_accession","study_accession","sample_accession","base_count",
-        "instrument_model","library_strategy","scientific_name","first_public","study_title",
-    ].join(",");
+/// Accessions per `study_accession` query, keeping URLs well under common length limits.
+const ACCESSION_CHUNK: usize = 50;
 
+/// Fetch the ONT runs of explicit study accessions, chunked `OR` queries, dedup by run.
+pub fn fetch_runs_for_studies(accessions: &[String], opts: &FetchOptions) -> Result<Vec<RunRecord>> {
+    let client = make_client(USER_AGENT, &opts.client)?;
+    preflight(opts)?;
+
+    let fields = opts.preset.fields();
     let mut dedup: HashSet<String> = HashSet::new();
     let mut out: Vec<RunRecord> = Vec::new();
 
-    let mut s = start;
-    while s <= end {
-        let e = std::cmp::min(s + chrono::Duration::days(13), end);
-        let q = format!(
-            r#"instrument_platform="OXFORD_NANOPORE" AND (first_public>={s} AND first_public<={e})"#,
-            s = s.format("%Y-%m-%d"),
-            e = e.format("%Y-%m-%d")
-        );
-        debug!("released-only window raw_query: {}", q);
-        let url = build_url(&q, &fields);
-        let r = request_with_retries(&client, &url)?;
-        if !r.status().is_success() { bail!("ENA search(read_run) failed: {} (released window {}..{})", r.status(), s, e); }
-        let mut runs: Vec<RunRecord> = r.json().context("decode read_run json (released window)")?;
-        for rec in runs.drain(..) {
-            if let Some(acc) = rec.run_accession.as_ref() {
-                if dedup.insert(acc.clone()) { out.push(rec); }
-            } else {
-                out.push(rec);
-            }
+    for chunk in accessions.chunks(ACCESSION_CHUNK) {
+        opts.check_deadline(Duration::ZERO, "between accession chunks")?;
+        let ors = chunk.iter().map(|a| format!(r#"study_accession="{}""#, a.replace('"', ""))).collect::<Vec<_>>().join(" OR ");
+        let q = format!(r#"instrument_platform="OXFORD_NANOPORE" AND ({})"#, ors);
+        debug!("accessions raw_query: {}", q);
+        let runs = window_search(&client, &q, &fields, opts, &format!("accessions {}..{}", chunk[0], chunk[chunk.len() - 1]))?;
+        let (returned, before) = (runs.len(), out.len());
+        push_new_runs(runs, &mut dedup, &mut out);
+        opts.record_window(format!("accessions {}..{}", chunk[0], chunk[chunk.len() - 1]), returned, out.len() - before);
+    }
+
+    info!("{} accessions -> {} runs", accessions.len(), out.len());
+    Ok(out)
+}
+
+/// Fetch the `analysis` rows of explicit study accessions (any platform), chunked `OR`
+/// queries, dedup by analysis accession; `--include-analyses`.
+pub fn fetch_analyses_for_studies(accessions: &[String], opts: &FetchOptions) -> Result<Vec<AnalysisRecord>> {
+    let client = make_client(USER_AGENT, &opts.client)?;
+    let mut dedup: HashSet<String> = HashSet::new();
+    let mut out: Vec<AnalysisRecord> = Vec::new();
+
+    for chunk in accessions.chunks(ACCESSION_CHUNK) {
+        opts.check_deadline(Duration::ZERO, "between analysis chunks")?;
+        let q = chunk.iter().map(|a| format!(r#"study_accession="{}""#, a.replace('"', ""))).collect::<Vec<_>>().join(" OR ");
+        let what = format!("analyses {}..{}", chunk[0], chunk[chunk.len() - 1]);
+        debug!("analyses raw_query: {}", q);
+        opts.stats.borrow_mut().current = Some(what.clone());
+        let found = search_pages::<AnalysisRecord>(&client, "analysis", &q, ANALYSIS_FIELDS, opts, &what);
+        opts.stats.borrow_mut().current = None;
+        let found = found?;
+        let (returned, before) = (found.len(), out.len());
+        out.extend(found.into_iter().filter(|a| a.analysis_accession.as_ref().map_or(true, |acc| dedup.insert(acc.clone()))));
+        opts.record_window(what, returned, out.len() - before);
+    }
+
+    info!("{} accessions -> {} analyses", accessions.len(), out.len());
+    Ok(out)
+}
+
+/// Fetch runs within a **fixed release** window: `first_public ∈ [start, end]`.
+pub fn fetch_runs_between(start: chrono::NaiveDate, end: chrono::NaiveDate, opts: &FetchOptions) -> Result<Vec<RunRecord>> {
+    fetch_runs_in_windows(&[(start, end)], opts)
+}
+
+/// Fetch several fixed release windows (each `[start, end]`, inclusive) in order, de-duplicating
+/// by run across all of them, so overlapping windows contribute each run once.
+pub fn fetch_runs_in_windows(windows: &[(chrono::NaiveDate, chrono::NaiveDate)], opts: &FetchOptions) -> Result<Vec<RunRecord>> {
+    fetch_windows(windows, None, opts)
+}
+
+/// ENA query clause matching `term` anywhere in `study_title` or `scientific_name`
+/// (`*` wildcards; ENA compares text case-insensitively). Quotes are dropped from `term`.
+pub fn text_match_clause(term: &str) -> String {
+    let t = term.replace('"', "");
+    format!(r#"(study_title="*{t}*" OR scientific_name="*{t}*")"#, t = t.trim())
+}
+
+/// Fetch ONT runs whose study title or species mentions `term` (see [`text_match_clause`]).
+///
+/// With no `windows` this is one search over every release date; otherwise each
+/// window is split like [`fetch_runs_in_windows`] with the text clause added.
+pub fn fetch_runs_matching(term: &str, windows: &[(chrono::NaiveDate, chrono::NaiveDate)], opts: &FetchOptions) -> Result<Vec<RunRecord>> {
+    let clause = text_match_clause(term);
+    if !windows.is_empty() { return fetch_windows(windows, Some(&clause), opts); }
+    let client = make_client(USER_AGENT, &opts.client)?;
+    preflight(opts)?;
+    let q = format!(r#"instrument_platform="OXFORD_NANOPORE" AND {}"#, clause);
+    debug!("text search raw_query: {}", q);
+    let runs = window_search(&client, &q, &opts.preset.fields(), opts, "text search")?;
+    let (returned, mut out) = (runs.len(), Vec::new());
+    push_new_runs(runs, &mut HashSet::new(), &mut out);
+    opts.record_window("text search".to_string(), returned, out.len());
+    info!("text search {:?} -> {} runs", term, out.len());
+    Ok(out)
+}
+
+/// Released-only windows, each split into 14-day searches, plus an optional extra
+/// clause ANDed onto every query; one dedup set across all of them.
+fn fetch_windows(windows: &[(chrono::NaiveDate, chrono::NaiveDate)], extra: Option<&str>, opts: &FetchOptions) -> Result<Vec<RunRecord>> {
+    let client = make_client(USER_AGENT, &opts.client)?;
+    preflight(opts)?;
+
+    let fields = opts.preset.fields();
+
+    let mut dedup: HashSet<String> = HashSet::new();
+    let mut out: Vec<RunRecord> = Vec::new();
+
+    for &(start, end) in windows {
+        let (searched, before) = (opts.stats.borrow().windows.len(), out.len());
+        let mut s = start;
+        while s <= end {
+            opts.check_deadline(Duration::ZERO, "between windows")?;
+            let e = std::cmp::min(s + chrono::Duration::days(13), end);
+            let mut q = format!(
+                r#"instrument_platform="OXFORD_NANOPORE" AND (first_public>={s} AND first_public<={e})"#,
+                s = s.format("%Y-%m-%d"),
+                e = e.format("%Y-%m-%d")
+            );
+            if let Some(extra) = extra { q.push_str(" AND "); q.push_str(extra); }
+            debug!("released-only window raw_query: {}", q);
+            let runs = window_search(&client, &q, &fields, opts, &format!("released window {}..{}", s, e)).map_err(|err| err.in_window(s, e))?;
+            let (returned, before) = (runs.len(), out.len());
+            push_new_runs(runs, &mut dedup, &mut out);
+            opts.record_window(format!("{}..{}", s, e), returned, out.len() - before);
+            s = e + chrono::Duration::days(1);
         }
-        s = e + chrono::Duration::days(1);
+        info!("released-only window {}..{} -> {} runs", start, end, out.len() - before);
+        let mut stats = opts.stats.borrow_mut();
+        let returned = stats.windows[searched..].iter().map(|w| w.runs).sum();
+        let possibly_truncated = stats.windows[searched..].iter().any(|w| w.possibly_truncated);
+        stats.spans.push(WindowStat { label: format!("{}..{}", start, end), runs: returned, new: out.len() - before, possibly_truncated });
     }
 
-    info!("released-only window {}..{} -> {} runs", start, end, out.len());
     Ok(out)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    /// A canned answer from [`serve`].
+    struct Reply {
+        content_type: &'static str,
+        content_encoding: Option<&'static str>,
+        body: Vec<u8>,
+    }
+
+    /// Answer every request on a local port with `reply`, recording each request head.
+    /// Returns the portal base to point a [`ClientConfig`] at.
+    fn serve(reply: Reply) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&seen);
+        thread::spawn(move || {
+            for mut stream in listener.incoming().map_while(std::result::Result::ok) {
+                let mut head = Vec::new();
+                let mut byte = [0u8; 1];
+                while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).is_ok_and(|n| n == 1) { head.push(byte[0]); }
+                log.lock().unwrap().push(String::from_utf8_lossy(&head).into_owned());
+                let mut out = format!("HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n", reply.content_type, reply.body.len());
+                if let Some(enc) = reply.content_encoding { out.push_str(&format!("Content-Encoding: {}\r\n", enc)); }
+                out.push_str("\r\n");
+                let _ = stream.write_all(out.as_bytes()).and_then(|_| stream.write_all(&reply.body));
+            }
+        });
+        (base, seen)
+    }
+
+    fn local(base: String) -> FetchOptions {
+        FetchOptions { client: ClientConfig { portal_base: Some(base), allow_insecure_scheme: true, ..ClientConfig::default() }, ..FetchOptions::default() }
+    }
+
+    fn runs_json(runs: &[(&str, &str)]) -> Vec<u8> {
+        let rows: Vec<serde_json::Value> = runs.iter().map(|(run, study)| serde_json::json!({ "run_accession": run, "study_accession": study, "instrument_model": "MinION" })).collect();
+        serde_json::to_vec(&rows).unwrap()
+    }
+
+    #[test]
+    fn map_platform_labels() {
+        let cases = [
+            ("PromethION 2 Solo", "PromethION P2"),
+            ("promethion p2 solo", "PromethION P2"),
+            ("P2 Solo", "PromethION P2"),
+            ("p2", "PromethION P2"),
+            ("PromethION", "PromethION"),
+            ("PROMETHION 24", "PromethION"),
+            ("GridION", "GridION"),
+            ("Flongle", "Flongle"),
+            ("MinION Flongle", "Flongle"),
+            ("fLoNgLe", "Flongle"),
+            ("MinION", "MinION"),
+            ("minion mk1c", "MinION"),
+            ("Oxford Nanopore", "Oxford Nanopore"),
+            ("unspecified", "Oxford Nanopore"),
+        ];
+        for (model, label) in cases { assert_eq!(map_platform(Some(model)), label, "{:?}", model); }
+        assert_eq!(map_platform(None), "Oxford Nanopore");
+    }
+
+    #[test]
+    fn collection_dates_parse_leniently() {
+        let d = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let cases = [
+            ("2021", Some(("2021-01-01", "2021-12-31"))),
+            ("2021-02", Some(("2021-02-01", "2021-02-28"))),
+            ("2020-02", Some(("2020-02-01", "2020-02-29"))),
+            ("2021-12", Some(("2021-12-01", "2021-12-31"))),
+            ("2021-03-04", Some(("2021-03-04", "2021-03-04"))),
+            (" 2021-03-04T10:00Z ", Some(("2021-03-04", "2021-03-04"))),
+            ("2020/2021-03", Some(("2020-01-01", "2021-03-31"))),
+            ("2021-01-05/2021-01-07", Some(("2021-01-05", "2021-01-07"))),
+            ("2021-02-30", None),
+            ("2021-13", None),
+            ("2021/2020", None),
+            ("2021/missing", None),
+            ("missing", None),
+            ("not collected", None),
+            ("", None),
+            ("04/03/2021", None),
+        ];
+        for (raw, want) in cases { assert_eq!(parse_collection_date(raw), want.map(|(a, b)| (d(a), d(b))), "{:?}", raw); }
+    }
+
+    #[test]
+    fn ont_models_match_exactly() {
+        for m in ["MinION", "promethion  2 solo", " GridION ", "P2", "minion mk1c"] { assert_eq!(is_ont_model(m), Some(true), "{:?}", m); }
+        // Substrings that map_platform would accept are not known models.
+        for m in ["Illumina NovaSeq 6000", "Ion Torrent S5", "ONT", "MinION-like", "Oxford Nanopore"] { assert_eq!(is_ont_model(m), Some(false), "{:?}", m); }
+        for m in ["", "  ", "unspecified", "Unspecified"] { assert_eq!(is_ont_model(m), None, "{:?}", m); }
+    }
+
+    #[test]
+    fn html_answer_is_not_json() {
+        let page = b"<html>\n<body>Service temporarily unavailable</body>\n</html>".to_vec();
+        let (base, _) = serve(Reply { content_type: "text/html; charset=utf-8", content_encoding: None, body: page });
+        let opts = local(base);
+        let client = make_client(USER_AGENT, &opts.client).unwrap();
+        let err = search_runs(&client, "q", "run_accession", &opts, "window 2026-01-01..2026-01-14").unwrap_err();
+        assert!(matches!(err, EnaError::NotJson { .. }), "{:?}", err);
+        assert!(err.is_network());
+        assert_eq!(err.to_string(), concat!(
+            "ENA returned text/html; charset=utf-8 instead of JSON for ENA search(read_run) (window 2026-01-01..2026-01-14), ",
+            "body starts: <html> <body>Service temporarily unavailable</body> </html>",
+        ));
+    }
+
+    #[test]
+    fn full_window_drops_repeated_runs() {
+        let body = runs_json(&[("ERR1", "PRJEB1"), ("ERR2", "PRJEB1"), ("ERR1", "PRJEB1"), ("ERR3", "PRJEB2"), ("ERR2", "PRJEB1")]);
+        let (base, seen) = serve(Reply { content_type: "application/json", content_encoding: None, body });
+        let opts = local(base);
+        let (since, today) = (NaiveDate::from_ymd_opt(2026, 1, 1).unwrap(), NaiveDate::from_ymd_opt(2026, 1, 31).unwrap());
+        let runs = fetch_runs_since(since, today, false, &opts).unwrap();
+        let accs: Vec<_> = runs.iter().filter_map(|r| r.run_accession.as_deref()).collect();
+        assert_eq!(accs, ["ERR1", "ERR2", "ERR3"]);
+        let stats = opts.stats.borrow();
+        assert_eq!((stats.windows.len(), stats.windows[0].runs, stats.windows[0].new), (1, 5, 3));
+        // Ping and handshake, then the single full-window search.
+        assert_eq!(seen.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn window_failure_keeps_its_cause_in_the_chain() {
+        let day = |d: u32| NaiveDate::from_ymd_opt(2026, 1, d).unwrap();
+        let http = EnaError::Http { status: StatusCode::SERVICE_UNAVAILABLE, url: "http://x/search".into(), what: "ENA search(read_run) (released window 2026-01-01..2026-01-14)".into() };
+        let err = http.in_window(day(1), day(14));
+        assert!(err.is_network());
+        let cause = std::error::Error::source(&err).and_then(|e| e.downcast_ref::<Box<EnaError>>());
+        assert!(matches!(cause.map(Box::as_ref), Some(EnaError::Http { .. })), "{:?}", cause);
+        assert_eq!(format!("{:#}", anyhow::Error::new(err)), "window 2026-01-01..2026-01-14: ENA search(read_run) (released window 2026-01-01..2026-01-14) failed: 503 Service Unavailable");
+        let deadline = EnaError::Deadline("deadline of 1s exceeded".into()).in_window(day(1), day(14));
+        assert!(matches!(deadline, EnaError::Deadline(_)));
+    }
+
+    #[test]
+    fn rate_limit_clones_share_one_bucket_across_threads() {
+        let limit = RateLimit::per_second(10.0);
+        // Every thread asks at the same instant, so thread start-up skew cannot move the slots.
+        let t0 = Instant::now();
+        let handles: Vec<_> = (0..4).map(|_| { let l = limit.clone(); thread::spawn(move || l.reserve_at(t0)) }).collect();
+        let mut slots: Vec<Instant> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        slots.sort_unstable();
+        // One slot every 100ms however the threads interleave.
+        let expected: Vec<Instant> = (0..4).map(|i| t0 + Duration::from_millis(100) * i).collect();
+        assert_eq!(slots, expected);
+    }
+
+    #[test]
+    fn gzip_search_body_is_decoded() {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&runs_json(&[("ERR1", "PRJEB1"), ("ERR2", "PRJEB1")])).unwrap();
+        let (base, seen) = serve(Reply { content_type: "application/json", content_encoding: Some("gzip"), body: gz.finish().unwrap() });
+        let opts = local(base);
+        let client = make_client(USER_AGENT, &opts.client).unwrap();
+        let runs = search_runs(&client, "q", "run_accession", &opts, "test").unwrap();
+        let accs: Vec<_> = runs.iter().filter_map(|r| r.run_accession.as_deref()).collect();
+        assert_eq!(accs, ["ERR1", "ERR2"]);
+        assert!(seen.lock().unwrap()[0].to_ascii_lowercase().contains("accept-encoding: gzip"));
+    }
+}
@@ -0,0 +1,20 @@
+//! herring as a library: the ENA client and the per-study aggregation behind the
+//! `herring` binary.
+//!
+//! ```no_run
+//! use herring::ena::{fetch_runs_since, FetchOptions};
+//! use herring::study::{aggregate, AggregateOptions};
+//!
+//! let since = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+//! let today = chrono::Utc::now().date_naive();
+//! let runs = fetch_runs_since(since, today, false, &FetchOptions::default())?;
+//! let (rows, _unparseable) = aggregate(&runs, &AggregateOptions::default());
+//! for r in rows { println!("{} {}", r.acc, r.species); }
+//! # Ok::<(), herring::ena::EnaError>(())
+//! ```
+
+#![deny(unsafe_code)]
+#![warn(missing_docs)]
+
+pub mod ena;
+pub mod study;
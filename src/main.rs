@@ -3,19 +3,29 @@
 #![warn(missing_docs)]
 
 use anyhow::{Context, Result, bail};
-use clap::{ArgAction, Parser, Subcommand};
-use chrono::{Duration, Utc, NaiveDate};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use chrono::{Datelike, Duration, Utc, NaiveDate};
 use polars::prelude::*;
-use polars::prelude::SortMultipleOptions;
-use log::info;
+use log::{debug, info, warn};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 
-mod ena;
-use ena::{fetch_runs_since, fetch_runs_between, map_platform, map_strategy, RunRecord};
+use herring::ena::{
+    client_env, default_timeout_secs, doctor, fetch_runs_raw, fetch_runs_since, fetch_runs_between, fetch_runs_in_windows,
+    fetch_runs_for_studies, fetch_runs_matching, fetch_analyses_for_studies, is_ont_model, map_platform, probe_field_list,
+    probe_fields, map_strategy, parse_collection_date, portal_base, post_json, Checkpoint, ClientConfig, Deadline, EnaToken,
+    FetchOptions, FieldStatus, FieldsPreset, EnaError, RateLimit, RunRecord, ENA_ROW_CAP, PLATFORMS,
+};
+use herring::study::{
+    aggregate, group, AggregateOptions, BaseDedup, GroupBy, GroupRow, NumFormat, PlatformFilter, Row, SpeciesFilter, SpeciesOrder, StrategyFilter,
+};
+
+/// `println!` for report output: stdout, or the open `--pager` session (see [`emit`]).
+macro_rules! outln { ($($t:tt)*) => { emit(&format!("{}\n", format_args!($($t)*))) } }
 
 #[derive(Parser, Debug)]
 #[command(name = "herring", version, about = "List recent ENA studies with Oxford Nanopore data")]
@@ -29,49 +39,737 @@ struct Cli {
 /// Top-level commands for `herring`.
 enum Commands {
     /// List studies released or updated in the last N weeks (default: 8).
-    List {
-        /// Weeks back from today (UTC) OR used as the window length with --from.
-        #[arg(short, long, default_value_t = 8)]
-        weeks: i64,
-        /// Start date (YYYY-MM-DD) for a fixed release window. Uses first_public between FROM and FROM+weeks.
-        #[arg(long, value_name="YYYY-MM-DD")]
-        from: Option<String>,
-        /// End date (YYYY-MM-DD) for a fixed release window; requires --from. Inclusive.
-        #[arg(long, value_name="YYYY-MM-DD")]
-        to: Option<String>,
-        /// Increase log verbosity: -v (info), -vv (debug)
-        #[arg(short, long, action = ArgAction::Count)]
-        verbose: u8,
-        /// Write CSV to path
-        #[arg(long)]
-        csv: Option<PathBuf>,
-        /// Write JSON to path
-        #[arg(long)]
-        json: Option<PathBuf>,
-        /// Write HTML to path (sortable table)
-        #[arg(long)]
-        html: Option<PathBuf>,
-    },
-}
-
-/// Initialize env_logger with a default filter from verbosity flags.
-fn init_logger(verbosity: u8) {
+    List(Box<ListArgs>),
+    /// Refresh stats for an explicit list of study accessions (ONT runs only).
+    Accessions(Box<AccessionsArgs>),
+    /// List ONT studies whose study title or species mentions TERM, over all releases or a date window.
+    Search(Box<SearchArgs>),
+    /// Poll the rolling window every N minutes and print studies that were not in the previous poll.
+    Watch(WatchArgs),
+    /// Check connectivity and client settings (env vars, proxy, TLS) and print PASS/FAIL per step.
+    Doctor(DoctorArgs),
+    /// Print name, version, ENA portal base and default timeout as one JSON object.
+    Version,
+}
+
+#[derive(Args, Debug)]
+/// Options for the `list` command.
+struct ListArgs {
+    /// Weeks back from today (UTC) OR used as the window length with --from.
+    #[arg(short, long, default_value_t = 8)]
+    weeks: i64,
+    /// Start date (YYYY-MM-DD) for a fixed release window. Uses first_public between FROM and FROM+weeks.
+    /// Repeat for several windows; their runs are merged (each run once).
+    #[arg(long, value_name="YYYY-MM-DD")]
+    from: Vec<String>,
+    /// End date (YYYY-MM-DD) for a fixed release window; requires --from. Inclusive.
+    /// With several --from, give one --to per --from (paired in order) or none.
+    #[arg(long, value_name="YYYY-MM-DD")]
+    to: Vec<String>,
+    /// Incremental rolling mode: start from the newest first_public recorded in this state file
+    /// (falls back to --weeks when the file is missing) and update it after a successful run.
+    #[arg(long, value_name="PATH", conflicts_with = "from")]
+    state: Option<PathBuf>,
+    /// Treat a future-dated window (start or end after today, UTC) as an error instead of a warning.
+    #[arg(long)]
+    strict_window: bool,
+    /// Rolling window only: runs updated in the window but first public before it (re-releases, metadata updates).
+    #[arg(long, conflicts_with_all = ["from", "state"])]
+    changed_only: bool,
+    /// Refuse windows starting before this date unless --force (guards against runaway scans).
+    #[arg(long, value_name="YYYY-MM-DD", value_parser = ymd_arg, default_value = "2010-01-01")]
+    min_date: NaiveDate,
+    /// Run even when the window starts before --min-date.
+    #[arg(long)]
+    force: bool,
+    /// Compute windows as if today (UTC) were this date, for reproducible runs (default: the current UTC date, read once).
+    #[arg(long, value_name="YYYY-MM-DD", value_parser = ymd_arg)]
+    today: Option<NaiveDate>,
+    /// Render a previous --json export from PATH instead of querying ENA (offline; no date window).
+    #[arg(long, value_name="PATH", conflicts_with_all = ["from", "to", "state", "changed_only"])]
+    input: Option<PathBuf>,
+    /// [advanced, unsupported] Send QUERY verbatim as the ENA read_run search (no window, no ONT clause) and report on the result.
+    #[arg(long, value_name="QUERY", conflicts_with_all = ["from", "to", "state", "changed_only", "input"])]
+    raw_query: Option<String>,
+    #[command(flatten)]
+    report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+/// Options for the `accessions` command.
+struct AccessionsArgs {
+    /// Study accessions to refresh (e.g. PRJEB12345 PRJNA67890).
+    #[arg(value_name="ACCESSION")]
+    accessions: Vec<String>,
+    /// Read more accessions from FILE, one per line (blank lines and `#` comments ignored).
+    #[arg(long, value_name="FILE")]
+    file: Option<PathBuf>,
+    #[command(flatten)]
+    report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+/// Options for the `search` command.
+struct SearchArgs {
+    /// Text to look for in study_title or scientific_name (e.g. Plasmodium); matched anywhere, per ENA's text rules.
+    #[arg(value_name="TERM")]
+    term: String,
+    /// Only studies released in the last N weeks (default: every release date).
+    #[arg(short, long)]
+    weeks: Option<i64>,
+    /// Start date (YYYY-MM-DD) of a release window, as for `list` (window length --weeks, default 8). Repeatable.
+    #[arg(long, value_name="YYYY-MM-DD")]
+    from: Vec<String>,
+    /// End date (YYYY-MM-DD) of a release window; requires --from. Inclusive.
+    #[arg(long, value_name="YYYY-MM-DD")]
+    to: Vec<String>,
+    /// Treat a future-dated window (start or end after today, UTC) as an error instead of a warning.
+    #[arg(long)]
+    strict_window: bool,
+    /// Refuse windows starting before this date unless --force.
+    #[arg(long, value_name="YYYY-MM-DD", value_parser = ymd_arg, default_value = "2010-01-01")]
+    min_date: NaiveDate,
+    /// Run even when the window starts before --min-date.
+    #[arg(long)]
+    force: bool,
+    /// Compute windows as if today (UTC) were this date, for reproducible runs (default: the current UTC date, read once).
+    #[arg(long, value_name="YYYY-MM-DD", value_parser = ymd_arg)]
+    today: Option<NaiveDate>,
+    #[command(flatten)]
+    report: ReportArgs,
+}
+
+#[derive(Args, Debug)]
+/// Filtering, output and logging options shared by commands that print a study table.
+struct ReportArgs {
+    /// Keep only runs whose sample collection_date overlaps on/after this date (YYYY-MM-DD).
+    #[arg(long, value_name="YYYY-MM-DD", value_parser = ymd_arg)]
+    collected_from: Option<NaiveDate>,
+    /// Keep only runs whose sample collection_date overlaps on/before this date (YYYY-MM-DD).
+    #[arg(long, value_name="YYYY-MM-DD", value_parser = ymd_arg)]
+    collected_to: Option<NaiveDate>,
+    /// Keep studies where any scientific_name contains SUBSTR (case-insensitive). Repeatable; OR within the flag.
+    #[arg(long, value_name="SUBSTR")]
+    species: Vec<String>,
+    /// Drop studies where any scientific_name contains SUBSTR (case-insensitive). Repeatable; applied after --species.
+    #[arg(long, value_name="SUBSTR")]
+    exclude_species: Vec<String>,
+    /// Cut each scientific_name to genus + species (first two words) before listing, grouping and ranking species,
+    /// so strains and serovars collapse. JSON keeps the raw names in scientific_names.
+    #[arg(long)]
+    normalize_species: bool,
+    /// Show runs without a scientific_name as LABEL (e.g. "(unspecified)") in the species column and
+    /// species groups, instead of leaving them out (default: empty; groups then use "(unknown)").
+    #[arg(long, value_name="LABEL", default_value = "")]
+    unknown_species_label: String,
+    /// Order of each study's species list (and so which 5 the column shows): alpha, or count (most runs first).
+    #[arg(long, value_enum, value_name="ORDER", default_value_t = SpeciesOrder::Alpha)]
+    species_order: SpeciesOrder,
+    /// Keep studies where any run's center_name contains SUBSTR (case-insensitive). Repeatable; OR within the flag, AND with the species filters.
+    #[arg(long, value_name="SUBSTR")]
+    center: Vec<String>,
+    /// Keep studies with at least one run on platform NAME (as shown in the platform column, case-insensitive). Repeatable.
+    #[arg(long, value_name="NAME", value_parser = platform_arg)]
+    platform: Vec<String>,
+    /// Drop studies whose runs are all on excluded platforms (mixed studies stay). Repeatable; applied after --platform.
+    #[arg(long, value_name="NAME", value_parser = platform_arg)]
+    exclude_platform: Vec<String>,
+    /// Keep studies whose runs span at least N distinct platforms (as in the platform column, e.g. MinION + PromethION).
+    #[arg(long, value_name="N", value_parser = clap::value_parser!(u8).range(1..=6))]
+    min_platforms: Option<u8>,
+    /// Keep studies with at least one run in sequencing type NAME (a map_strategy bucket such as genome,
+    /// transcriptome, metagenome, or another lowercased library_strategy). Repeatable.
+    #[arg(long, value_name="NAME", value_parser = strategy_arg)]
+    strategy: Vec<String>,
+    /// Drop studies with any run in sequencing type NAME (mixed studies go too). Repeatable; applied after --strategy.
+    #[arg(long, value_name="NAME", value_parser = strategy_arg)]
+    exclude_strategy: Vec<String>,
+    /// Drop runs whose instrument_model is missing or unrecognized (the generic "Oxford Nanopore" label) before aggregating.
+    #[arg(long)]
+    drop_unknown_platform: bool,
+    /// Report runs whose instrument_model is not a known ONT model (e.g. a mislabelled Illumina run); `drop` also removes them.
+    /// Blank and "unspecified" models are reported as unknown and kept. Bare flag means warn.
+    #[arg(long, value_enum, value_name="MODE", num_args = 0..=1, default_missing_value = "warn")]
+    strict_ont: Option<StrictOnt>,
+    /// Aggregate by study (default), platform, species or center. Non-study rows carry summed gigabases and distinct study/biosample counts.
+    #[arg(long, value_enum, value_name="KEY", default_value_t = GroupBy::Study)]
+    group_by: GroupBy,
+    /// One row per ISO week (`2024-W07`) of study release date, oldest first: study count, summed biosamples, runs and gigabases.
+    #[arg(long, conflicts_with = "group_by")]
+    by_week: bool,
+    /// Sum base_count over every run (default), or once per sample using its largest run value (re-basecalled samples).
+    #[arg(long, value_enum, value_name="KEY", default_value_t = BaseDedup::Run)]
+    dedup_bases_by: BaseDedup,
+    /// Drop studies with fewer than N distinct biosamples.
+    #[arg(long, value_name="N")]
+    biosamples_min: Option<u32>,
+    /// Drop studies with more than N distinct biosamples.
+    #[arg(long, value_name="N")]
+    biosamples_max: Option<u32>,
+    /// Abort the whole fetch (all windows and retries) once N seconds of wall-clock time have elapsed.
+    #[arg(long, value_name="N")]
+    deadline_secs: Option<u64>,
+    #[command(flatten)]
+    client: ClientArgs,
+    /// Page ENA searches N rows at a time (limit/offset) until a short page, instead of one unbounded request.
+    #[arg(long, value_name="N", value_parser = clap::value_parser!(u64).range(1..))]
+    page_size: Option<u64>,
+    /// Send at most N requests per second (fractions allowed, e.g. 0.5), retries and the handshake included. Requests are serial (no --jobs), so this is the process rate.
+    #[arg(long, value_name="PER_SEC", value_parser = rate_arg)]
+    rate_limit: Option<f64>,
+    /// On a failing window, log it and carry on; failed windows are listed at the end and the exit code is 3.
+    #[arg(long)]
+    keep_partial: bool,
+    /// Record each completed window's runs in PATH and, when restarted with the same PATH, skip the
+    /// windows already there. The file is removed once the output is written and no window failed.
+    #[arg(long, value_name="PATH")]
+    resume: Option<PathBuf>,
+    /// Abort when the startup handshake (results ping + 1-record search) fails, instead of warning.
+    #[arg(long)]
+    require_handshake: bool,
+    /// Increase log verbosity: -v (info), -vv (debug)
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// Log errors only and never page stdout (overrides --pager).
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Also write structured JSON log records (info and above, regardless of -v) to PATH, one per line.
+    #[arg(long, value_name="PATH")]
+    log_json: Option<PathBuf>,
+    /// Where --html species names link to: Wikipedia search, NCBI Taxonomy (by tax_id when known) or nowhere.
+    #[arg(long, value_enum, value_name="TARGET", default_value_t = SpeciesLink::Wikipedia)]
+    species_link: SpeciesLink,
+    /// Shorthand for --species-link none.
+    #[arg(long, conflicts_with_all = ["species_link_base", "species_link"])]
+    no_species_links: bool,
+    /// Prefix for name-based --html species links; the percent-encoded name is appended.
+    #[arg(long, value_name="URL")]
+    species_link_base: Option<String>,
+    /// Write every fetched run (all requested fields, before filtering and aggregation) as CSV to PATH.
+    #[arg(long, value_name="PATH")]
+    runs_csv: Option<PathBuf>,
+    /// Tag each study `new` or `seen` against every accession ever recorded in PATH, then add this run's studies to it.
+    #[arg(long, value_name="PATH")]
+    seen_db: Option<PathBuf>,
+    /// Write CSV to path
+    #[arg(long)]
+    csv: Option<PathBuf>,
+    /// Append to an existing --csv file without repeating the header (header must match).
+    #[arg(long, requires = "csv")]
+    append: bool,
+    /// Write one CSV/JSON row per (study, species) pair instead of a comma-joined species list.
+    /// Other columns repeat the study-level values (biosamples, gigabases, ... are not split per species).
+    #[arg(long, alias = "flatten-species")]
+    explode_species: bool,
+    /// Start new CSV files with a UTF-8 byte-order mark so Excel reads non-ASCII names correctly.
+    #[arg(long)]
+    csv_bom: bool,
+    /// Compress the CSV/JSON exports (and --runs-csv), appending .gz/.zst to each path. HTML stays plain.
+    #[arg(long, value_enum, value_name="CODEC", default_value_t = Compress::None, conflicts_with = "append")]
+    compress: Compress,
+    /// Write JSON to path
+    #[arg(long)]
+    json: Option<PathBuf>,
+    /// Write --json minified (a single-line array, same shape) instead of pretty-printed.
+    #[arg(long)]
+    compact: bool,
+    /// Indent pretty --json output by N spaces (0 = same as --compact).
+    #[arg(long, value_name="N", default_value_t = 2, value_parser = clap::value_parser!(u8).range(0..=16), conflicts_with = "compact")]
+    json_indent: u8,
+    /// Key naming for --json, --counts-json and --webhook: snake (study_accession) or camel (studyAccession).
+    #[arg(long, value_enum, value_name="STYLE", default_value_t = JsonKeys::Snake)]
+    json_keys: JsonKeys,
+    /// Write HTML to path (sortable table)
+    #[arg(long)]
+    html: Option<PathBuf>,
+    /// Embed the --json rows in the --html page (as a JSON script block) with a "Download JSON" button.
+    #[arg(long)]
+    html_embed_data: bool,
+    /// Write every export format to DIR/BASENAME.<ext> (created if missing); explicit --csv/--json/--html paths win.
+    #[arg(long, value_name="DIR")]
+    output_dir: Option<PathBuf>,
+    /// Comma-separated export formats written under --output-dir (default: all)
+    #[arg(long, value_name="FORMATS", value_delimiter = ',', requires = "output_dir")]
+    formats: Vec<ExportFormat>,
+    /// File stem used with --output-dir [default: herring-YYYYMMDD, today UTC]
+    #[arg(long, value_name="NAME", requires = "output_dir")]
+    basename: Option<String>,
+    /// Show gigabases with thousands separators and Gb/Tb/Pb units in stdout/CSV/HTML (JSON stays numeric).
+    #[arg(long)]
+    human_sizes: bool,
+    /// Decimal places for gigabases in stdout/CSV/HTML text and the rounded JSON number (--human-sizes text keeps one).
+    #[arg(long, value_name="N", default_value_t = 1, value_parser = clap::value_parser!(u8).range(0..=6))]
+    gigabases_precision: u8,
+    /// Write gigabases with a decimal comma (1234,5) in stdout/CSV/HTML; CSV then uses `;` between fields. JSON stays numeric.
+    #[arg(long)]
+    decimal_comma: bool,
+    /// Truncate stdout cells longer than N characters with an ellipsis (exports keep full text).
+    #[arg(long, value_name="N", value_parser = clap::value_parser!(u64).range(1..))]
+    max_col_width: Option<u64>,
+    /// Colorize the stdout table: auto (only on a terminal, honoring NO_COLOR), always, or never.
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// Draw the stdout table with box-drawing borders, shrinking wide text columns to fit $COLUMNS.
+    #[arg(long)]
+    pretty: bool,
+    /// Show stdout tables through $PAGER (default `less -FRS`) when stdout is a terminal; ignored when piped.
+    #[arg(long)]
+    pager: bool,
+    /// After fetching, print each query window's date range (or accession chunk) and run counts, plus totals and retries.
+    #[arg(long)]
+    stats: bool,
+    /// Print a one-line summary (studies, runs, biosamples, gigabases, unparseable base counts) after the table.
+    #[arg(long)]
+    summary: bool,
+    /// Write the --summary totals (studies, runs, biosamples, gigabases) as a small JSON object to PATH, even when nothing matched.
+    #[arg(long, value_name="PATH")]
+    counts_json: Option<PathBuf>,
+    /// After the table, print the N species with the most gigabases across the surviving runs, with study counts.
+    #[arg(long, value_name="N", value_parser = clap::value_parser!(u32).range(1..))]
+    top_species: Option<u32>,
+    /// POST the JSON rows (same shape as --json) to this webhook URL after listing.
+    #[arg(long, value_name="URL")]
+    webhook: Option<String>,
+    /// Treat a failed or non-2xx --webhook POST as an error instead of a warning.
+    #[arg(long, requires = "webhook")]
+    webhook_strict: bool,
+    /// Exit with an error (nonzero status) when no studies match, instead of a friendly message.
+    #[arg(long)]
+    fail_on_empty: bool,
+    /// Add library_source and library_selection columns to stdout/CSV/HTML (always present in JSON).
+    #[arg(long)]
+    show_library_details: bool,
+    /// Add a raw_models column (distinct raw instrument_model strings) next to platform in stdout/CSV/HTML.
+    #[arg(long)]
+    include_platform_column_raw: bool,
+    /// Add a release_span column (earliest .. latest first_public of the study's runs) to stdout/CSV/HTML.
+    #[arg(long)]
+    show_span: bool,
+    /// Add a sample_titles column (up to 5 distinct sample titles per study) to stdout/CSV/HTML (full list in JSON).
+    #[arg(long)]
+    show_sample_titles: bool,
+    /// Also query ENA's analysis result type (assemblies, consensus sequences) for the listed studies and add an analyses column.
+    #[arg(long)]
+    include_analyses: bool,
+    /// ENA field set: minimal (accession, date, bases; no filters that need other fields), standard,
+    /// or full (adds read_count; center, collection_date, tax_id and reads columns).
+    #[arg(long, value_enum, value_name="PRESET", default_value_t = FieldsPreset::Standard)]
+    fields_preset: FieldsPreset,
+    /// Omit the study_title column from stdout, CSV, JSON and HTML.
+    #[arg(long)]
+    no_title: bool,
+    /// Comma-separated columns, in order, for stdout and every export (overrides the column flags).
+    #[arg(long, value_name="LIST", value_delimiter = ',', value_parser = column_arg,
+        conflicts_with_all = ["show_library_details", "include_platform_column_raw", "show_span", "show_sample_titles", "no_title"])]
+    columns: Vec<Column>,
+    /// Order rows by this column (numbers and dates largest/newest first, text A-Z; ties keep date order). Also marks the HTML's sorted column.
+    #[arg(long, value_name="COLUMN", value_parser = column_arg)]
+    sort_by: Option<Column>,
+    /// What was queried (window, accessions, ...), for the HTML header; set by the command.
+    #[arg(skip)]
+    query_label: String,
+}
+
+#[derive(Args, Debug)]
+/// Options for the `watch` command.
+struct WatchArgs {
+    /// Minutes to wait between polls (at most 10080, one week).
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..=10_080))]
+    interval_mins: u64,
+    /// Rolling window length in weeks (released OR updated).
+    #[arg(short, long, default_value_t = 8)]
+    weeks: i64,
+    /// Refuse windows starting before this date unless --force (guards against runaway scans).
+    #[arg(long, value_name="YYYY-MM-DD", value_parser = ymd_arg, default_value = "2010-01-01")]
+    min_date: NaiveDate,
+    /// Run even when the window starts before --min-date.
+    #[arg(long)]
+    force: bool,
+    /// Increase log verbosity: -v (info), -vv (debug)
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// Also write structured JSON log records (info and above, regardless of -v) to PATH, one per line.
+    #[arg(long, value_name="PATH")]
+    log_json: Option<PathBuf>,
+    #[command(flatten)]
+    client: ClientArgs,
+}
+
+#[derive(Args, Debug)]
+/// Options for the `doctor` command.
+struct DoctorArgs {
+    /// Increase log verbosity: -v (info), -vv (debug)
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// After the checks, report which read_run fields ENA knows and returns populated (one sample ONT run).
+    #[arg(long)]
+    probe: bool,
+    /// Field set to probe, as for list (minimal, standard, full).
+    #[arg(long, value_enum, value_name="PRESET", default_value_t = FieldsPreset::Standard, requires = "probe")]
+    fields_preset: FieldsPreset,
+    /// Extra read_run field name to probe. Repeatable.
+    #[arg(long, value_name="NAME", requires = "probe")]
+    probe_field: Vec<String>,
+    #[command(flatten)]
+    client: ClientArgs,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+/// HTTP client overrides shared by every command that talks to ENA.
+struct ClientArgs {
+    /// Request timeout in seconds. Precedence: this flag, then HERRING_TIMEOUT_SECS, then 30.
+    #[arg(long, value_name="N", value_parser = clap::value_parser!(u64).range(1..))]
+    timeout_secs: Option<u64>,
+    /// PEM file of extra root CA(s) to trust. Beats HERRING_CA_BUNDLE.
+    #[arg(long, value_name="PATH")]
+    ca_bundle: Option<PathBuf>,
+    /// Disable TLS certificate validation (debugging only). Same as HERRING_INSECURE_TLS=1.
+    #[arg(long)]
+    insecure_tls: bool,
+    /// ENA Portal API base URL (mirrors, proxies, test servers). Beats HERRING_PORTAL_BASE. Must be https.
+    #[arg(long, value_name="URL")]
+    portal_base: Option<String>,
+    /// Accept a plain http:// portal base (logged as a warning) instead of refusing it.
+    #[arg(long)]
+    allow_insecure_scheme: bool,
+    /// Credential for the ENA Portal API (user:password for HTTP Basic, else a Bearer token). Beats HERRING_ENA_TOKEN.
+    /// Prefer the env var: flags are visible in the process list.
+    #[arg(long, value_name="TOKEN")]
+    ena_token: Option<EnaToken>,
+}
+
+impl ClientArgs {
+    fn config(&self) -> ClientConfig {
+        ClientConfig {
+            timeout_secs: self.timeout_secs, ca_bundle: self.ca_bundle.clone(), insecure_tls: self.insecure_tls,
+            portal_base: self.portal_base.clone(), allow_insecure_scheme: self.allow_insecure_scheme, ena_token: self.ena_token.clone(),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+/// Export formats selectable with `--formats`.
+enum ExportFormat {
+    Csv,
+    Json,
+    Html,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+/// Encoder wrapped around CSV/JSON export files by `--compress`.
+enum Compress {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compress {
+    /// `path` with the codec's extension appended (`out.csv` → `out.csv.gz`).
+    fn path(self, path: PathBuf) -> PathBuf {
+        let ext = match self { Compress::None => return path, Compress::Gzip => ".gz", Compress::Zstd => ".zst" };
+        let mut p = path.into_os_string();
+        p.push(ext);
+        p.into()
+    }
+
+    /// Create (truncate) `path` behind this codec's encoder.
+    fn create(self, path: &std::path::Path) -> Result<ExportFile> {
+        let f = std::io::BufWriter::new(File::create(path).with_context(|| format!("create {}", path.display()))?);
+        Ok(match self {
+            Compress::None => ExportFile::Plain(f),
+            Compress::Gzip => ExportFile::Gzip(flate2::write::GzEncoder::new(f, flate2::Compression::default())),
+            Compress::Zstd => ExportFile::Zstd(zstd::Encoder::new(f, 0)?),
+        })
+    }
+}
+
+/// An export file opened by [`Compress::create`]; call [`ExportFile::finish`] to
+/// write the compressed trailer, or the file ends up truncated.
+enum ExportFile {
+    Plain(std::io::BufWriter<File>),
+    Gzip(flate2::write::GzEncoder<std::io::BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, std::io::BufWriter<File>>),
+}
+
+impl Write for ExportFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self { ExportFile::Plain(w) => w.write(buf), ExportFile::Gzip(w) => w.write(buf), ExportFile::Zstd(w) => w.write(buf) }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self { ExportFile::Plain(w) => w.flush(), ExportFile::Gzip(w) => w.flush(), ExportFile::Zstd(w) => w.flush() }
+    }
+}
+
+impl ExportFile {
+    /// Finish the encoder (if any) and flush everything to disk.
+    fn finish(self) -> Result<()> {
+        let mut f = match self { ExportFile::Plain(w) => w, ExportFile::Gzip(w) => w.finish()?, ExportFile::Zstd(w) => w.finish()? };
+        f.flush()?;
+        Ok(())
+    }
+
+    /// Unwrap a CSV writer built on an export file and finish it.
+    fn finish_csv(wtr: csv::Writer<ExportFile>) -> Result<()> {
+        wtr.into_inner().map_err(|e| e.into_error())?.finish()
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Key naming for the JSON outputs; `--json-keys`.
+enum JsonKeys {
+    /// `study_accession` (the documented schema).
+    #[default]
+    Snake,
+    /// `studyAccession`, for JavaScript consumers.
+    Camel,
+}
+
+impl JsonKeys {
+    /// `value` serialized with this key style.
+    fn wrap<T: Serialize + ?Sized>(self, value: &T) -> Keyed<'_, T> {
+        Keyed { value, keys: self }
+    }
+}
+
+/// `snake_case` → `camelCase`.
+fn camel_case(key: &str) -> String {
+    let mut parts = key.split('_');
+    let mut out = parts.next().unwrap_or_default().to_string();
+    for p in parts {
+        let mut c = p.chars();
+        if let Some(first) = c.next() { out.extend(first.to_uppercase()); out.push_str(c.as_str()); }
+    }
+    out
+}
+
+/// `camelCase` → `snake_case`; snake keys come back unchanged.
+fn snake_case(key: &str) -> String {
+    let mut out = String::with_capacity(key.len() + 4);
+    for ch in key.chars() {
+        if ch.is_ascii_uppercase() { out.push('_'); out.push(ch.to_ascii_lowercase()); } else { out.push(ch); }
+    }
+    out
+}
+
+/// Rename every object key in `v` (recursively) with `f`.
+fn rename_keys(v: serde_json::Value, f: fn(&str) -> String) -> serde_json::Value {
+    match v {
+        serde_json::Value::Object(m) => serde_json::Value::Object(m.into_iter().map(|(k, v)| (f(&k), rename_keys(v, f))).collect()),
+        serde_json::Value::Array(a) => serde_json::Value::Array(a.into_iter().map(|v| rename_keys(v, f)).collect()),
+        other => other,
+    }
+}
+
+/// A JSON output under a [`JsonKeys`] style. Snake case serializes `value` as is;
+/// camel case goes through a `serde_json::Value` so every shape is covered by one rename.
+struct Keyed<'a, T: ?Sized> {
+    value: &'a T,
+    keys: JsonKeys,
+}
+
+impl<T: Serialize + ?Sized> Serialize for Keyed<'_, T> {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.keys {
+            JsonKeys::Snake => self.value.serialize(s),
+            JsonKeys::Camel => {
+                let v = serde_json::to_value(self.value).map_err(serde::ser::Error::custom)?;
+                rename_keys(v, camel_case).serialize(s)
+            }
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+/// When to colorize the stdout table.
+enum ColorChoice {
+    /// Color only when stdout is a terminal and `NO_COLOR` is unset.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Resolve against the environment.
+    fn enabled(self) -> bool {
+        use std::io::IsTerminal;
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => std::env::var_os("NO_COLOR").map_or(true, |v| v.is_empty()) && std::io::stdout().is_terminal(),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+/// Link target for species names in the HTML export.
+enum SpeciesLink {
+    Wikipedia,
+    Ncbi,
+    None,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+/// What `--strict-ont` does with runs on a non-ONT instrument model.
+enum StrictOnt {
+    Warn,
+    Drop,
+}
+
+/// Initialize env_logger with a default filter from verbosity flags (`quiet`: errors only).
+///
+/// With `log_json`, records are additionally written as JSON lines to that file
+/// (see [`TeeLogger`]); stderr output is unchanged.
+fn init_logger(verbosity: u8, quiet: bool, log_json: Option<&std::path::Path>) -> Result<()> {
     use env_logger::Env;
-    let level = match verbosity { 0 => "warn", 1 => "info", _ => "debug" };
+    let level = match verbosity { _ if quiet => "error", 0 => "warn", 1 => "info", _ => "debug" };
     let env = Env::default().default_filter_or(level);
     let mut b = env_logger::Builder::from_env(env);
     b.format_timestamp_secs();
-    let _ = b.try_init();
+    let Some(path) = log_json else {
+        let _ = b.try_init();
+        return Ok(());
+    };
+    let file = File::create(path).with_context(|| format!("create --log-json file {}", path.display()))?;
+    let stderr = b.build();
+    let max = stderr.filter().max(TeeLogger::JSON_LEVEL);
+    log::set_boxed_logger(Box::new(TeeLogger { stderr, json: std::sync::Mutex::new(std::io::LineWriter::new(file)) }))
+        .context("install logger")?;
+    log::set_max_level(max);
+    Ok(())
+}
+
+/// Logger that forwards to env_logger (stderr) and appends JSON lines to a file.
+///
+/// Each line is `{"timestamp","level","target","message"}` with an RFC 3339 UTC timestamp.
+struct TeeLogger {
+    stderr: env_logger::Logger,
+    json: std::sync::Mutex<std::io::LineWriter<File>>,
+}
+
+impl TeeLogger {
+    /// Fixed file level, independent of `-v`.
+    const JSON_LEVEL: log::LevelFilter = log::LevelFilter::Info;
+}
+
+impl log::Log for TeeLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.stderr.enabled(metadata) || metadata.level() <= Self::JSON_LEVEL
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.stderr.matches(record) { self.stderr.log(record); }
+        if record.level() <= Self::JSON_LEVEL {
+            let line = serde_json::json!({
+                "timestamp": Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            if let Ok(mut w) = self.json.lock() { let _ = writeln!(w, "{}", line); }
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+        if let Ok(mut w) = self.json.lock() { let _ = w.flush(); }
+    }
+}
+
+/// Log (at `-vv`) which request or window an ENA error came from; the message
+/// itself only carries ENA's label for it.
+fn log_failure_site(e: &EnaError) {
+    match e {
+        EnaError::WindowFailed { start, end, source } => { debug!("failed window: {}..{}", start, end); log_failure_site(source) }
+        EnaError::Handshake { source, .. } => log_failure_site(source),
+        EnaError::Http { status, url, .. } => debug!("failed request: {} -> {}", url, status),
+        _ => {}
+    }
+}
+
+/// Exit status for a failed run: 2 when a network [`EnaError`] is anywhere in the chain
+/// (worth retrying), 3 for a [`PartialFetch`] (output written, some windows missing),
+/// 1 for usage, input and data errors.
+fn exit_code(err: &anyhow::Error) -> u8 {
+    if err.chain().any(|e| e.is::<PartialFetch>()) { 3 } else if err.chain().any(|e| e.downcast_ref::<EnaError>().is_some_and(EnaError::is_network)) { 2 } else { 1 }
+}
+
+#[derive(Debug)]
+/// `--keep-partial` finished with some windows missing; the report covers the rest.
+struct PartialFetch {
+    failed: Vec<String>,
 }
 
+impl std::fmt::Display for PartialFetch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} window(s) failed, output is partial:", self.failed.len())?;
+        for w in &self.failed { write!(f, "\n  - {}", w)?; }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PartialFetch {}
+
 /// Entry point.
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+fn main() -> std::process::ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            // --help/--version print to stdout and succeed; usage errors exit 1 (clap's default is 2).
+            let _ = e.print();
+            return std::process::ExitCode::from(if e.use_stderr() { 1 } else { 0 });
+        }
+    };
+    match run(cli) {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            if let Some(ena) = e.chain().find_map(|e| e.downcast_ref::<EnaError>()) { log_failure_site(ena); }
+            eprintln!("Error: {:?}", e);
+            std::process::ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+#[derive(Serialize)]
+/// `herring version` output, for deployment tracking.
+struct VersionInfo {
+    name: &'static str,
+    version: &'static str,
+    ena_portal_base: String,
+    default_timeout_secs: u64,
+}
+
+/// Print [`VersionInfo`] as a single JSON line on stdout.
+fn print_version() -> Result<()> {
+    let info = VersionInfo { name: env!("CARGO_PKG_NAME"), version: env!("CARGO_PKG_VERSION"), ena_portal_base: portal_base(), default_timeout_secs: default_timeout_secs() };
+    println!("{}", serde_json::to_string(&info)?);
+    Ok(())
+}
+
+/// Dispatch a parsed command line.
+fn run(cli: Cli) -> Result<()> {
     match cli.command {
-        Commands::List { weeks, from, to, verbose, csv, json, html } => {
-            init_logger(verbose);
-            list_studies(weeks, from, to, csv, json, html)?
+        Commands::List(args) => {
+            init_logger(args.report.verbose, args.report.quiet, args.report.log_json.as_deref())?;
+            paged(args.report.paging(), || list_studies(*args))?
+        }
+        Commands::Accessions(args) => {
+            init_logger(args.report.verbose, args.report.quiet, args.report.log_json.as_deref())?;
+            paged(args.report.paging(), || list_accessions(*args))?
         }
+        Commands::Search(args) => {
+            init_logger(args.report.verbose, args.report.quiet, args.report.log_json.as_deref())?;
+            paged(args.report.paging(), || search_studies(*args))?
+        }
+        Commands::Watch(args) => {
+            init_logger(args.verbose, false, args.log_json.as_deref())?;
+            watch_studies(args)?
+        }
+        Commands::Doctor(args) => {
+            init_logger(args.verbose, false, None)?;
+            run_doctor(args)?
+        }
+        Commands::Version => print_version()?,
     }
     Ok(())
 }
@@ -83,209 +781,1543 @@ struct OutRow<'a> {
     release_date: &'a str,
     platform: &'a str,
     sequencing_type: &'a str,
+    library_source: &'a str,
+    library_selection: &'a str,
     species: &'a str,
     biosamples: u32,
-    gigabases: f64,
-    study_title: &'a str,
+    gigabases: Option<f64>,
+    fastq_bytes: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    study_title: Option<&'a str>,
+    sample_titles: &'a [String],
+    scientific_names: &'a [String],
+    fastq_ftp: String,
+    /// `new`/`seen` under `--seen-db`, absent otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seen: Option<&'static str>,
+    /// ENA analyses under `--include-analyses`, absent otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    analyses: Option<u32>,
 }
 
-#[derive(Clone)]
-/// Internal aggregation row used for building tables/exports.
-struct Row {
-    acc: String,
-    release: String,
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Owned counterpart of [`OutRow`], read back by `--input`.
+struct InRow {
+    study_accession: String,
+    release_date: String,
     platform: String,
-    seq_type: String,
+    sequencing_type: String,
+    library_source: String,
+    library_selection: String,
     species: String,
     biosamples: u32,
-    gigabases_num: f64,
-    gigabases_str: String,
-    title: String,
+    /// `null` when no run reported a base count.
+    gigabases: Option<f64>,
+    fastq_bytes: u128,
+    /// Absent in `--no-title` exports.
+    #[serde(default)]
+    study_title: String,
+    /// Absent in exports made before sample titles were requested.
+    #[serde(default)]
+    sample_titles: Vec<String>,
+    /// Absent in exports made before raw names were kept.
+    #[serde(default)]
+    scientific_names: Vec<String>,
+    fastq_ftp: String,
+    /// Present in `--seen-db` exports; re-derived, not trusted, on reload.
+    #[serde(default, rename = "seen")]
+    _seen: Option<String>,
+    /// Present in `--include-analyses` exports.
+    #[serde(default)]
+    analyses: Option<u32>,
 }
 
-/// Execute the listing workflow and print/export results.
-fn list_studies(weeks: i64, from: Option<String>, to: Option<String>, csv: Option<PathBuf>, json: Option<PathBuf>, html: Option<PathBuf>) -> Result<()> {
-    let runs: Vec<RunRecord> = if let Some(from_s) = from {
-        let start = NaiveDate::parse_from_str(&from_s, "%Y-%m-%d")
-            .with_context(|| format!("--from must be YYYY-MM-DD, got: {}", from_s))?;
-        let end_inclusive = if let Some(to_s) = to {
-            let to_d = NaiveDate::parse_from_str(&to_s, "%Y-%m-%d")
-                .with_context(|| format!("--to must be YYYY-MM-DD, got: {}", to_s))?;
-            if to_d < start { bail!("--to ({}) is before --from ({})", to_d, start); }
-            to_d
+impl InRow {
+    /// Rebuild a display row. Run counts, raw models, tax ids, collection dates and the
+    /// release span are not in the export, so they come back empty.
+    fn into_row(self, num: NumFormat) -> Row {
+        let bases = (self.gigabases.unwrap_or(0.0).max(0.0) * 1e9) as u128;
+        Row {
+            acc: self.study_accession,
+            release_last: self.release_date.clone(),
+            release: self.release_date,
+            platform: self.platform,
+            raw_models: String::new(),
+            seq_type: self.sequencing_type,
+            lib_source: self.library_source,
+            lib_selection: self.library_selection,
+            // Exports keep only the (at most 5) listed names.
+            species_names: self.species.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect(),
+            species: self.species,
+            centers: String::new(),
+            tax_ids: BTreeMap::new(),
+            collected: None,
+            biosamples: self.biosamples,
+            gigabases_num: self.gigabases.map(|g| num.round(g)),
+            gigabases_str: num.gigabases(self.gigabases, bases),
+            bases,
+            reads: 0,
+            run_count: 0,
+            fastq_bytes: self.fastq_bytes,
+            title: self.study_title,
+            sample_titles: self.sample_titles,
+            scientific_names: self.scientific_names,
+            seen: None,
+            analyses: self.analyses,
+            fastq: self.fastq_ftp.split(';').map(str::trim).filter(|f| !f.is_empty()).map(str::to_string).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// One output column; headers and cell text for stdout, CSV and HTML come from here.
+enum Column {
+    StudyAccession,
+    Seen,
+    ReleaseDate,
+    ReleaseSpan,
+    Platform,
+    RawModels,
+    SequencingType,
+    LibrarySource,
+    LibrarySelection,
+    Species,
+    SampleTitles,
+    Center,
+    Collected,
+    TaxIds,
+    Biosamples,
+    Analyses,
+    Reads,
+    Gigabases,
+    Size,
+    StudyTitle,
+    FastqFtp,
+}
+
+impl Column {
+    /// Every column, in default display order.
+    const ALL: [Column; 21] = [
+        Column::StudyAccession, Column::Seen, Column::ReleaseDate, Column::ReleaseSpan, Column::Platform, Column::RawModels, Column::SequencingType,
+        Column::LibrarySource, Column::LibrarySelection, Column::Species, Column::SampleTitles, Column::Center, Column::Collected, Column::TaxIds,
+        Column::Biosamples, Column::Analyses, Column::Reads, Column::Gigabases, Column::Size, Column::StudyTitle, Column::FastqFtp,
+    ];
+
+    /// Look a column up by its header name.
+    fn by_name(name: &str) -> Option<Column> {
+        Column::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    /// Header / export field name.
+    fn name(self) -> &'static str {
+        match self {
+            Column::StudyAccession => "study_accession",
+            Column::Seen => "seen",
+            Column::ReleaseDate => "release_date",
+            Column::ReleaseSpan => "release_span",
+            Column::Platform => "platform",
+            Column::RawModels => "raw_models",
+            Column::SequencingType => "sequencing_type",
+            Column::LibrarySource => "library_source",
+            Column::LibrarySelection => "library_selection",
+            Column::Species => "species",
+            Column::SampleTitles => "sample_titles",
+            Column::Center => "center",
+            Column::Collected => "collection_date",
+            Column::TaxIds => "tax_id",
+            Column::Biosamples => "biosamples",
+            Column::Analyses => "analyses",
+            Column::Reads => "reads",
+            Column::Gigabases => "gigabases",
+            Column::Size => "size",
+            Column::StudyTitle => "study_title",
+            Column::FastqFtp => "fastq_ftp",
+        }
+    }
+
+    /// Sort type used by the HTML table script (`str`, `date` or `num`).
+    fn kind(self) -> &'static str {
+        match self {
+            Column::ReleaseDate | Column::Collected => "date",
+            Column::Biosamples | Column::Analyses | Column::Reads | Column::Gigabases | Column::Size | Column::FastqFtp => "num",
+            _ => "str",
+        }
+    }
+
+    /// Numeric value behind a `num` column (`None`: no data, e.g. unreported gigabases).
+    fn number(self, r: &Row) -> Option<f64> {
+        match self {
+            Column::Biosamples => Some(r.biosamples as f64),
+            Column::Analyses => r.analyses.map(f64::from),
+            Column::Reads => Some(r.reads as f64),
+            Column::Gigabases => r.gigabases_num,
+            Column::Size => Some(r.fastq_bytes as f64),
+            Column::FastqFtp => Some(r.fastq.len() as f64),
+            _ => None,
+        }
+    }
+
+    /// Human-formatted cell text for a row.
+    fn text(self, r: &Row) -> String {
+        match self {
+            Column::StudyAccession => r.acc.clone(),
+            Column::Seen => match r.seen { Some(true) => "seen", Some(false) => "new", None => "" }.to_string(),
+            Column::ReleaseDate => r.release.clone(),
+            Column::ReleaseSpan => if r.release_last.is_empty() || r.release_last == r.release { r.release.clone() } else { format!("{} .. {}", r.release, r.release_last) },
+            Column::Platform => r.platform.clone(),
+            Column::RawModels => r.raw_models.clone(),
+            Column::SequencingType => r.seq_type.clone(),
+            Column::LibrarySource => r.lib_source.clone(),
+            Column::LibrarySelection => r.lib_selection.clone(),
+            Column::Species => r.species.clone(),
+            Column::SampleTitles => r.sample_titles.iter().take(5).cloned().collect::<Vec<_>>().join("; "),
+            Column::Center => r.centers.clone(),
+            Column::Collected => match r.collected {
+                Some((lo, hi)) if lo == hi => lo.to_string(),
+                Some((lo, hi)) => format!("{} .. {}", lo, hi),
+                None => String::new(),
+            },
+            Column::TaxIds => r.tax_ids.values().collect::<BTreeSet<_>>().into_iter().map(String::as_str).collect::<Vec<_>>().join(", "),
+            Column::Biosamples => r.biosamples.to_string(),
+            Column::Analyses => r.analyses.map_or_else(String::new, |n| n.to_string()),
+            Column::Reads => r.reads.to_string(),
+            Column::Gigabases => r.gigabases_str.clone(),
+            Column::Size => format_size(r.fastq_bytes),
+            Column::StudyTitle => r.title.clone(),
+            Column::FastqFtp => r.fastq.join(";"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// Optional columns switched by flags.
+struct ColumnFlags {
+    library_details: bool,
+    raw_models: bool,
+    span: bool,
+    sample_titles: bool,
+    /// `--seen-db` is in use.
+    seen: bool,
+    /// `--include-analyses` counts (or an export carrying them) are present.
+    analyses: bool,
+    no_title: bool,
+    preset: FieldsPreset,
+}
+
+/// Columns rendered to stdout/CSV/HTML, in display order.
+///
+/// `fastq_ftp` is only requested by the file exports; it is far too wide for stdout.
+/// The `minimal` preset only has what it fetched: accession, date and gigabases.
+fn table_columns(flags: ColumnFlags, include_fastq: bool) -> Vec<Column> {
+    let mut cols = vec![Column::StudyAccession];
+    if flags.seen { cols.push(Column::Seen); }
+    cols.push(Column::ReleaseDate);
+    if flags.preset == FieldsPreset::Minimal { cols.push(Column::Gigabases); return cols; }
+    if flags.span { cols.push(Column::ReleaseSpan); }
+    cols.push(Column::Platform);
+    if flags.raw_models { cols.push(Column::RawModels); }
+    cols.push(Column::SequencingType);
+    if flags.library_details { cols.extend([Column::LibrarySource, Column::LibrarySelection]); }
+    if flags.preset == FieldsPreset::Full {
+        cols.extend([Column::Species, Column::Center, Column::Collected, Column::TaxIds, Column::Biosamples]);
+    } else {
+        cols.extend([Column::Species, Column::Biosamples]);
+    }
+    if flags.analyses { cols.push(Column::Analyses); }
+    if flags.preset == FieldsPreset::Full { cols.push(Column::Reads); }
+    cols.extend([Column::Gigabases, Column::Size]);
+    if flags.sample_titles { cols.insert(cols.iter().position(|&c| c == Column::Species).map_or(cols.len(), |i| i + 1), Column::SampleTitles); }
+    if !flags.no_title { cols.push(Column::StudyTitle); }
+    if include_fastq { cols.push(Column::FastqFtp); }
+    cols
+}
+
+/// `--sort-by`: stable re-sort of the date-ordered rows. Numbers and dates descend
+/// (missing numbers last), text ascends case-insensitively.
+fn sort_rows(rows: &mut [Row], col: Column) {
+    match col.kind() {
+        "num" => rows.sort_by(|a, b| match (col.number(a), col.number(b)) {
+            (Some(x), Some(y)) => y.total_cmp(&x),
+            (x, y) => y.is_some().cmp(&x.is_some()),
+        }),
+        "date" => rows.sort_by_cached_key(|r| std::cmp::Reverse(col.text(r))),
+        _ => rows.sort_by_cached_key(|r| col.text(r).to_lowercase()),
+    }
+}
+
+/// Clap value parser for `--platform`/`--exclude-platform`: a [`PLATFORMS`] label, any case.
+fn platform_arg(s: &str) -> std::result::Result<String, String> {
+    PLATFORMS.iter().find(|p| p.eq_ignore_ascii_case(s.trim())).map(|p| p.to_string())
+        .ok_or_else(|| format!("unknown platform; expected one of: {}", PLATFORMS.join(", ")))
+}
+
+/// Clap value parser for `--strategy`/`--exclude-strategy`: a lowercased [`map_strategy`] bucket.
+///
+/// Raw strategies that map into another bucket (`WGS`, `AMPLICON`, `RNA-Seq`, ...) are rejected
+/// rather than silently widened to that whole bucket.
+fn strategy_arg(s: &str) -> std::result::Result<String, String> {
+    let name = s.trim().to_ascii_lowercase();
+    if name.is_empty() { return Err("empty sequencing type".to_string()); }
+    let bucket = map_strategy(&name);
+    if bucket != name { return Err(format!("{:?} is a library_strategy reported as {:?}; filter on that sequencing type instead", s.trim(), bucket)); }
+    Ok(name)
+}
+
+/// Clap value parser for `--rate-limit`: requests per second, at least one per 1000 s.
+fn rate_arg(s: &str) -> std::result::Result<f64, String> {
+    let v: f64 = s.trim().parse().map_err(|e| format!("{}", e))?;
+    if !(v.is_finite() && v >= 0.001) { return Err("must be a number of requests per second, at least 0.001".to_string()); }
+    Ok(v)
+}
+
+/// Clap value parser for `--columns` entries.
+fn column_arg(s: &str) -> std::result::Result<Column, String> {
+    Column::by_name(s.trim()).ok_or_else(|| format!("unknown column; expected one of: {}", Column::ALL.map(Column::name).join(", ")))
+}
+
+/// Clap value parser for `YYYY-MM-DD` flags.
+fn ymd_arg(s: &str) -> std::result::Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|_| "expected YYYY-MM-DD".to_string())
+}
+
+/// Parse a `YYYY-MM-DD` flag value, naming the flag in the error.
+fn parse_ymd(flag: &str, s: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .with_context(|| format!("{} must be YYYY-MM-DD, got: {}", flag, s))
+}
+
+/// Keep runs whose `collection_date` overlaps `[from, to]` (either bound optional).
+///
+/// Unparseable or missing collection dates are dropped with a `debug!` line.
+fn filter_collected(runs: Vec<RunRecord>, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Vec<RunRecord> {
+    if from.is_none() && to.is_none() { return runs; }
+    let before = runs.len();
+    let kept: Vec<RunRecord> = runs.into_iter().filter(|r| {
+        let raw = r.collection_date.as_deref().unwrap_or("");
+        match parse_collection_date(raw) {
+            Some((lo, hi)) => from.map_or(true, |f| hi >= f) && to.map_or(true, |t| lo <= t),
+            None => {
+                debug!("skipping run {} with uninterpretable collection_date {:?}", r.run_accession.as_deref().unwrap_or("?"), raw);
+                false
+            }
+        }
+    }).collect();
+    info!("collection_date filter kept {} of {} runs", kept.len(), before);
+    kept
+}
+
+/// `--drop-unknown-platform`: keep only runs that [`map_platform`] places on a specific device.
+fn drop_unknown_platform(runs: Vec<RunRecord>) -> Vec<RunRecord> {
+    let before = runs.len();
+    let kept: Vec<RunRecord> = runs.into_iter().filter(|r| map_platform(r.instrument_model.as_deref()) != "Oxford Nanopore").collect();
+    info!("--drop-unknown-platform dropped {} of {} runs with a missing or unrecognized instrument_model", before - kept.len(), before);
+    kept
+}
+
+/// `--strict-ont`: warn about runs whose instrument_model is not a known ONT model ([`is_ont_model`]),
+/// dropping them in `drop` mode. Runs with a missing, blank or "unspecified" model are counted as unknown and kept.
+fn strict_ont(runs: Vec<RunRecord>, mode: StrictOnt) -> Vec<RunRecord> {
+    let model_check = |r: &RunRecord| r.instrument_model.as_deref().and_then(is_ont_model);
+    let mut models: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut unknown = 0;
+    for r in &runs {
+        match model_check(r) {
+            Some(true) => {}
+            Some(false) => *models.entry(r.instrument_model.as_deref().unwrap_or_default()).or_default() += 1,
+            None => unknown += 1,
+        }
+    }
+    if unknown > 0 { warn!("--strict-ont: {} run(s) have no instrument_model (blank or unspecified) and cannot be checked; kept", unknown); }
+    if models.is_empty() { return runs; }
+    let total: usize = models.values().sum();
+    let list = models.iter().map(|(m, n)| format!("{:?} ({})", m, n)).collect::<Vec<_>>().join(", ");
+    warn!("--strict-ont: {} run(s) report a non-ONT instrument_model: {}", total, list);
+    if mode == StrictOnt::Warn { return runs; }
+    let kept: Vec<RunRecord> = runs.into_iter().filter(|r| model_check(r) != Some(false)).collect();
+    warn!("--strict-ont drop removed {} run(s)", total);
+    kept
+}
+
+#[derive(Serialize, Debug)]
+/// Whole-result totals, printed by `--summary` and written by `--counts-json`.
+struct Summary {
+    studies: usize,
+    runs: u64,
+    biosamples: u64,
+    gigabases: f64,
+    unparseable_base_counts: usize,
+    /// Behind `gigabases`, for `--human-sizes` text.
+    #[serde(skip)]
+    bases: u128,
+}
+
+impl Summary {
+    /// Totals over the final (filtered) rows; gigabases are not rounded.
+    fn of(rows: &[Row], unparseable_base_counts: usize) -> Self {
+        let bases: u128 = rows.iter().fold(0u128, |acc, r| acc.saturating_add(r.bases));
+        Summary {
+            studies: rows.len(),
+            runs: rows.iter().map(|r| r.run_count as u64).sum(),
+            biosamples: rows.iter().map(|r| r.biosamples as u64).sum(),
+            // Unrounded: `--counts-json` feeds dashboards; text output rounds when printing.
+            gigabases: (bases as f64) / 1e9_f64,
+            unparseable_base_counts,
+            bases,
+        }
+    }
+
+    /// Total gigabases as text, formatted like the table's gigabases column, with its unit.
+    fn gigabases_text(&self, num: NumFormat) -> String {
+        if num.human_sizes { num.gigabases(Some(self.gigabases), self.bases) } else { format!("{} Gb", num.plain(self.gigabases)) }
+    }
+
+    /// The `--summary` line.
+    fn text(&self, num: NumFormat) -> String {
+        format!("Summary: {} studies, {} runs, {} biosamples, {}; {} unparseable base_count value(s)",
+            self.studies, self.runs, self.biosamples, self.gigabases_text(num), self.unparseable_base_counts)
+    }
+}
+
+/// Build the stdout DataFrame (gigabases as formatted string) for the given columns.
+fn rows_df(rows: &[Row], columns: &[Column]) -> Result<DataFrame> {
+    let series: Vec<Series> = columns.iter().map(|&c| match c {
+        Column::Biosamples => Series::new(c.name().into(), rows.iter().map(|r| r.biosamples).collect::<Vec<u32>>()),
+        _ => Series::new(c.name().into(), rows.iter().map(|r| c.text(r)).collect::<Vec<String>>()),
+    }).collect();
+    // Rows arrive sorted (newest first, ties by accession); the selected columns may not include the keys.
+    Ok(DataFrame::new(series)?)
+}
+
+#[derive(Serialize)]
+/// JSON shape of a [`GroupRow`].
+struct OutGroup<'a> {
+    group_by: &'static str,
+    key: &'a str,
+    studies: u32,
+    biosamples: u32,
+    runs: u32,
+    gigabases: f64,
+}
+
+/// Bucket the surviving studies by the ISO week of their release date, oldest first.
+/// Biosamples are summed per study (studies rarely share samples); unparseable dates land in `(unknown)`, last.
+fn week_rows(rows: &[Row]) -> Vec<GroupRow> {
+    let mut weeks: BTreeMap<String, GroupRow> = BTreeMap::new();
+    for r in rows {
+        let key = NaiveDate::parse_from_str(&r.release, "%Y-%m-%d").map_or_else(|_| "(unknown)".to_string(), |d| {
+            let w = d.iso_week();
+            format!("{}-W{:02}", w.year(), w.week())
+        });
+        let g = weeks.entry(key.clone()).or_insert_with(|| GroupRow { key, studies: 0, biosamples: 0, runs: 0, bases: 0 });
+        g.studies += 1;
+        g.biosamples = g.biosamples.saturating_add(r.biosamples);
+        g.runs = g.runs.saturating_add(r.run_count);
+        g.bases = g.bases.saturating_add(r.bases);
+    }
+    let mut out: Vec<GroupRow> = weeks.into_values().collect();
+    // '(' sorts before the digits of "YYYY-Www", so move "(unknown)" to the end.
+    if let Some(i) = out.iter().position(|g| g.key == "(unknown)") { let unknown = out.remove(i); out.push(unknown); }
+    out
+}
+
+/// `--top-species`: the species leaderboard over the runs of the surviving studies,
+/// reusing the `--group-by species` fold (blank names count as `(unknown)`).
+fn print_top_species(runs: &[RunRecord], rows: &[Row], n: u32, group_opts: &AggregateOptions, num: NumFormat, opts: &PrintOptions) -> Result<()> {
+    let mut top = group(runs, rows, &AggregateOptions { group_by: GroupBy::Species, ..*group_opts });
+    top.truncate(n as usize);
+    outln!("Top {} species by gigabases:", top.len());
+    print_df(&groups_df(&top, "species", num)?, opts)
+}
+
+/// Gigabases of a group, rounded like the study rows.
+fn group_gigabases(g: &GroupRow, num: NumFormat) -> f64 {
+    num.round((g.bases as f64) / 1e9_f64)
+}
+
+/// Stdout frame for `--group-by`/`--by-week` rows (already sorted); `key` names the key column.
+fn groups_df(groups: &[GroupRow], key: &'static str, num: NumFormat) -> Result<DataFrame> {
+    let gb = |g: &GroupRow| num.gigabases(Some(group_gigabases(g, num)), g.bases);
+    Ok(DataFrame::new(vec![
+        Series::new(key.into(), groups.iter().map(|g| g.key.clone()).collect::<Vec<String>>()),
+        Series::new("studies".into(), groups.iter().map(|g| g.studies).collect::<Vec<u32>>()),
+        Series::new("biosamples".into(), groups.iter().map(|g| g.biosamples).collect::<Vec<u32>>()),
+        Series::new("runs".into(), groups.iter().map(|g| g.runs).collect::<Vec<u32>>()),
+        Series::new("gigabases".into(), groups.iter().map(gb).collect::<Vec<String>>()),
+    ])?)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A validated query window.
+enum Window {
+    /// Released OR updated on/after `since`.
+    Rolling { since: NaiveDate },
+    /// Released (`first_public`) within `[start, end]`, inclusive.
+    Fixed { start: NaiveDate, end: NaiveDate },
+    /// Several fixed windows (repeated `--from`), fetched in order and merged.
+    Several(Vec<(NaiveDate, NaiveDate)>),
+}
+
+/// The date every window of this run is computed against: `--today`, else `now` (the
+/// current UTC date, read once by the caller so a run straddling midnight UTC stays
+/// self-consistent).
+fn resolve_today(pinned: Option<NaiveDate>, now: NaiveDate) -> Result<NaiveDate> {
+    match pinned {
+        Some(d) if d > now => bail!("--today {} is in the future (today is {} UTC)", d, now),
+        Some(d) => { info!("treating {} as today (--today; actual UTC date {})", d, now); Ok(d) }
+        None => Ok(now),
+    }
+}
+
+/// Validate `--weeks`/`--from`/`--to` together and resolve them against `today` (UTC).
+///
+/// Without `--from` the window is rolling (`today - weeks`); with it the window is
+/// fixed, ending at `--to` or at `FROM + weeks - 1 day`. A fixed window reaching
+/// past today is a warning, or an error when `strict`. Repeated `--from` give one
+/// window each, paired in order with `--to` (all or none), each validated on its own.
+///
+/// A start before `floor` (`--min-date`, `None` under `--force`) is refused: windowing
+/// that far back means hours of requests against ENA.
+fn resolve_window(weeks: i64, from: &[String], to: &[String], today: NaiveDate, strict: bool, floor: Option<NaiveDate>) -> Result<Window> {
+    let guard = |start: NaiveDate| check_min_date(start, floor);
+    if weeks < 1 {
+        bail!("--weeks must be at least 1, got {}", weeks);
+    }
+    if from.is_empty() {
+        if let Some(to) = to.first() {
+            bail!("--to {} requires --from (a fixed release window needs a start date; drop --to for a rolling window)", to);
+        }
+        let since = Duration::try_weeks(weeks).and_then(|d| today.checked_sub_signed(d))
+            .with_context(|| format!("--weeks {} reaches before the earliest representable date", weeks))?;
+        guard(since)?;
+        return Ok(Window::Rolling { since });
+    }
+    if !to.is_empty() && to.len() != from.len() {
+        bail!("--to given {} time(s) for {} --from; pair every --from with a --to (in order) or drop --to to use --weeks for each", to.len(), from.len());
+    }
+    let mut spans = Vec::with_capacity(from.len());
+    for (i, from) in from.iter().enumerate() {
+        let start = parse_ymd("--from", from)?;
+        guard(start)?;
+        let end = match to.get(i) {
+            Some(to) => {
+                let end = parse_ymd("--to", to)?;
+                if end < start { bail!("--to ({}) is before --from ({})", end, start); }
+                end
+            }
+            None => Duration::try_weeks(weeks).and_then(|d| start.checked_add_signed(d))
+                .and_then(|d| d.pred_opt())
+                .with_context(|| format!("--from {} plus --weeks {} overflows the calendar", start, weeks))?,
+        };
+        let problem = if start > today {
+            Some(format!("window {} .. {} starts in the future (today is {} UTC); nothing can have been released yet — check the year", start, end, today))
+        } else if end > today {
+            Some(format!("window {} .. {} ends in the future (today is {} UTC); results only cover up to today", start, end, today))
         } else {
-            (start + Duration::weeks(weeks)) - Duration::days(1)
+            None
         };
-        info!("released-only window: {} .. {} (inclusive)", start, end_inclusive);
-        fetch_runs_between(start, end_inclusive)?
+        if let Some(msg) = problem {
+            if strict { bail!("{} (--strict-window)", msg); }
+            warn!("{}", msg);
+        }
+        spans.push((start, end));
+    }
+    Ok(match spans[..] {
+        [(start, end)] => Window::Fixed { start, end },
+        _ => Window::Several(spans),
+    })
+}
+
+/// Refuse a window start before `floor` (`--min-date`; `None` under `--force`).
+fn check_min_date(start: NaiveDate, floor: Option<NaiveDate>) -> Result<()> {
+    match floor {
+        Some(floor) if start < floor => bail!(
+            "computed window start {} is before --min-date {}; scanning that far back takes hours of ENA requests — pass --force to run it anyway",
+            start, floor),
+        _ => Ok(()),
+    }
+}
+
+/// Check a `--state` high-water mark like any other window start: not before `floor`,
+/// and a mark after `today` (a stale or hand-edited file) warns, or fails when `strict`.
+fn check_state_mark(mark: NaiveDate, today: NaiveDate, strict: bool, floor: Option<NaiveDate>) -> Result<()> {
+    check_min_date(mark, floor).context("state file high-water mark")?;
+    if mark > today {
+        let msg = format!("state file high-water mark {} is in the future (today is {} UTC); nothing can have been released yet — fix or delete the file", mark, today);
+        if strict { bail!("{} (--strict-window)", msg); }
+        warn!("{}", msg);
+    }
+    Ok(())
+}
+
+/// Execute the listing workflow and print/export results.
+fn list_studies(args: ListArgs) -> Result<()> {
+    let ListArgs { weeks, from, to, state, strict_window, changed_only, min_date, force, today, input, raw_query, mut report } = args;
+    if let Some(path) = input {
+        report.resolve()?;
+        report.query_label = format!("saved rows from {}", path.display());
+        return render_input(&path, report);
+    }
+    if let Some(query) = raw_query {
+        if report.resume.is_some() { bail!("--resume checkpoints windowed scans; --raw-query is a single request"); }
+        report.resolve()?;
+        report.query_label = format!("raw query: {}", query);
+        let fetch_opts = report.fetch_options()?;
+        warn!("--raw-query is an unsupported debugging aid; the query is sent to ENA as-is");
+        let fetched = fetch_runs_raw(&query, &fetch_opts);
+        fetch_opts.log_traffic();
+        if report.stats { print_fetch_stats(&fetch_opts, &report.print_options())?; }
+        return report_studies(fetched?, report, &fetch_opts);
+    }
+    let today = resolve_today(today, Utc::now().date_naive())?;
+    let floor = if force { None } else { Some(min_date) };
+    let window = resolve_window(weeks, &from, &to, today, strict_window, floor)?;
+    let mark = match (&window, state.as_deref()) {
+        (Window::Rolling { .. }, Some(path)) => read_state(path)?,
+        _ => None,
+    };
+    if let Some(mark) = mark { check_state_mark(mark, today, strict_window, floor)?; }
+    report.resolve()?;
+    let fetch_opts = report.fetch_options()?;
+
+    let fetched = match window {
+        Window::Fixed { start, end } => {
+            info!("released-only window: {} .. {} (inclusive)", start, end);
+            report.query_label = format!("released {} .. {}", start, end);
+            fetch_runs_between(start, end, &fetch_opts)
+        }
+        Window::Several(spans) => {
+            let label = spans.iter().map(|(s, e)| format!("{} .. {}", s, e)).collect::<Vec<_>>().join(", ");
+            info!("released-only windows: {} (inclusive, merged)", label);
+            report.query_label = format!("released {}", label);
+            fetch_runs_in_windows(&spans, &fetch_opts)
+        }
+        Window::Rolling { since } => {
+            let (since, basis) = match mark {
+                Some(mark) => { info!("rolling window (released OR updated) since {} (from state file)", mark); (mark, "state file".to_string()) }
+                None => { info!("rolling window (released OR updated) since {} ({} weeks)", since, weeks); (since, format!("{} weeks", weeks)) }
+            };
+            let what = if changed_only { "updated (first public earlier)" } else { "released or updated" };
+            report.query_label = format!("{} since {} ({})", what, since, basis);
+            fetch_runs_since(since, today, changed_only, &fetch_opts)
+        }
+    };
+    fetch_opts.log_traffic();
+    let runs = fetched?;
+    if report.stats { print_fetch_stats(&fetch_opts, &report.print_options())?; }
+    let high_water = runs.iter().filter_map(|r| r.first_public.as_deref()).filter_map(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok()).max();
+    report_studies(runs, report, &fetch_opts)?;
+    // Leave the state file alone so the next run covers the failed windows again.
+    check_partial(&fetch_opts)?;
+    if let (Some(path), Some(high)) = (state, high_water) {
+        let mark = mark.map_or(high, |prev| prev.max(high));
+        write_state(&path, mark)?;
+        info!("state file {} high-water mark = {}", path.display(), mark);
+    }
+    Ok(())
+}
+
+/// `--stats`: one line per query window, then totals, repeated `--from` windows and retries.
+fn print_fetch_stats(opts: &FetchOptions, print_opts: &PrintOptions) -> Result<()> {
+    let stats = opts.stats.borrow();
+    let w = &stats.windows;
+    let df = DataFrame::new(vec![
+        Series::new("window".into(), w.iter().map(|s| s.label.clone()).collect::<Vec<String>>()),
+        Series::new("runs".into(), w.iter().map(|s| s.runs as u64).collect::<Vec<u64>>()),
+        Series::new("new".into(), w.iter().map(|s| s.new as u64).collect::<Vec<u64>>()),
+        Series::new("complete".into(), w.iter().map(|s| if s.possibly_truncated { "possibly truncated" } else { "yes" }).collect::<Vec<&str>>()),
+    ])?;
+    print_df(&df, print_opts)?;
+    outln!("Fetch: {} window(s), {} runs returned, {} unique, {} retries",
+        w.len(), w.iter().map(|s| s.runs).sum::<usize>(), w.iter().map(|s| s.new).sum::<usize>(), stats.retries);
+    let suspect = w.iter().filter(|s| s.possibly_truncated).count();
+    if suspect > 0 { outln!("{} window(s) returned a multiple of {} rows and may be truncated; re-run with --page-size", suspect, ENA_ROW_CAP); }
+    let spans = &stats.spans;
+    if spans.len() > 1 {
+        // "new" is after de-duplication against the earlier windows, so overlaps show up here.
+        outln!("Requested windows:");
+        print_df(&DataFrame::new(vec![
+            Series::new("window".into(), spans.iter().map(|s| s.label.clone()).collect::<Vec<String>>()),
+            Series::new("runs".into(), spans.iter().map(|s| s.runs as u64).collect::<Vec<u64>>()),
+            Series::new("new".into(), spans.iter().map(|s| s.new as u64).collect::<Vec<u64>>()),
+        ])?, print_opts)?;
+    }
+    let t = &stats.retry_reasons;
+    if !t.is_empty() {
+        outln!("Retries:");
+        print_df(&DataFrame::new(vec![
+            Series::new("request".into(), t.iter().map(|r| r.label.clone()).collect::<Vec<String>>()),
+            Series::new("reason".into(), t.iter().map(|r| r.reason.clone()).collect::<Vec<String>>()),
+            Series::new("count".into(), t.iter().map(|r| r.count).collect::<Vec<u32>>()),
+        ])?, print_opts)?;
+    }
+    Ok(())
+}
+
+/// `--input`: load a saved `--json` export and run only the print/export pipeline.
+///
+/// Flags that need run-level data are rejected rather than silently ignored.
+fn render_input(path: &std::path::Path, args: ReportArgs) -> Result<()> {
+    let run_level = [
+        ("--collected-from/--collected-to", args.collected_from.is_some() || args.collected_to.is_some()),
+        ("--runs-csv", args.runs_csv.is_some()),
+        ("--resume", args.resume.is_some()),
+        ("--include-analyses", args.include_analyses),
+        ("--species/--exclude-species", !args.species.is_empty() || !args.exclude_species.is_empty()),
+        ("--normalize-species", args.normalize_species),
+        ("--unknown-species-label", !args.unknown_species_label.is_empty()),
+        ("--species-order count", args.species_order == SpeciesOrder::Count),
+        ("--center", !args.center.is_empty()),
+        ("--platform/--exclude-platform", !args.platform.is_empty() || !args.exclude_platform.is_empty()),
+        ("--min-platforms", args.min_platforms.is_some()),
+        ("--strategy/--exclude-strategy", !args.strategy.is_empty() || !args.exclude_strategy.is_empty()),
+        ("--drop-unknown-platform", args.drop_unknown_platform),
+        ("--strict-ont", args.strict_ont.is_some()),
+        ("--group-by", args.group_by != GroupBy::Study),
+        ("--top-species", args.top_species.is_some()),
+        ("--dedup-bases-by", args.dedup_bases_by != BaseDedup::Run),
+    ];
+    if let Some((flag, _)) = run_level.iter().find(|(_, set)| *set) {
+        bail!("{} needs run-level data and cannot be used with --input", flag);
+    }
+    let text = std::fs::read_to_string(path).with_context(|| format!("read --input {}", path.display()))?;
+    // `--json-keys camel` exports are read back by folding the keys to snake case first.
+    let saved: Vec<InRow> = serde_json::from_str(&text).and_then(|v| serde_json::from_value(rename_keys(v, snake_case)))
+        .with_context(|| format!("--input {} is not a herring --json export (expected an array of study rows)", path.display()))?;
+    info!("loaded {} rows from {}", saved.len(), path.display());
+    let num = NumFormat { human_sizes: args.human_sizes, decimal_comma: args.decimal_comma, precision: args.gigabases_precision as usize };
+    let mut rows: Vec<Row> = saved.into_iter().map(|r| r.into_row(num)).collect();
+    rows.sort_by(|a, b| b.release.cmp(&a.release).then_with(|| a.acc.cmp(&b.acc)));
+    let fetch_opts = args.fetch_options()?;
+    report_rows(rows, 0, None, args, &fetch_opts)
+}
+
+#[derive(Serialize, Deserialize)]
+/// On-disk shape of the `--state` file.
+struct StateFile {
+    /// Newest `first_public` (YYYY-MM-DD) seen by a previous run.
+    last_first_public: String,
+}
+
+/// Read the `--state` high-water mark; a missing file means "first run".
+fn read_state(path: &std::path::Path) -> Result<Option<NaiveDate>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("read state file {}", path.display())),
+    };
+    let st: StateFile = serde_json::from_str(&text).with_context(|| format!("parse state file {}", path.display()))?;
+    Ok(Some(parse_ymd("state file last_first_public", &st.last_first_public)?))
+}
+
+/// Persist the `--state` high-water mark. The file is written next to `path` and renamed
+/// over it, so a crash mid-write leaves the previous mark rather than unparseable JSON.
+fn write_state(path: &std::path::Path, mark: NaiveDate) -> Result<()> {
+    let st = StateFile { last_first_public: mark.format("%Y-%m-%d").to_string() };
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    std::fs::write(&tmp, serde_json::to_string_pretty(&st)?).with_context(|| format!("write state file {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("replace state file {}", path.display()))
+}
+
+#[derive(Serialize, Deserialize)]
+/// On-disk shape of the `--seen-db` file.
+struct SeenDb {
+    /// Every study accession reported by a previous run, sorted.
+    studies: BTreeSet<String>,
+}
+
+/// Read the `--seen-db` accessions; a missing file means nothing was seen yet.
+fn read_seen_db(path: &std::path::Path) -> Result<BTreeSet<String>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeSet::new()),
+        Err(e) => return Err(e).with_context(|| format!("read seen db {}", path.display())),
+    };
+    let db: SeenDb = serde_json::from_str(&text).with_context(|| format!("parse seen db {}", path.display()))?;
+    Ok(db.studies)
+}
+
+/// Persist the `--seen-db` accessions.
+fn write_seen_db<'a>(path: &std::path::Path, studies: impl Iterator<Item = &'a String>) -> Result<()> {
+    let db = SeenDb { studies: studies.cloned().collect() };
+    std::fs::write(path, serde_json::to_string_pretty(&db)?).with_context(|| format!("write seen db {}", path.display()))?;
+    info!("seen db {} now lists {} studies", path.display(), db.studies.len());
+    Ok(())
+}
+
+/// Fetch the ONT runs of an explicit accession list and report them like `list`.
+fn list_accessions(args: AccessionsArgs) -> Result<()> {
+    let AccessionsArgs { mut accessions, file, mut report } = args;
+    if let Some(path) = file {
+        let text = std::fs::read_to_string(&path).with_context(|| format!("read accession file {}", path.display()))?;
+        accessions.extend(text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')).map(str::to_string));
+    }
+    let requested: BTreeSet<String> = accessions.into_iter().collect();
+    if requested.is_empty() { bail!("no accessions given (pass them as arguments or via --file)"); }
+    report.resolve()?;
+    let fetch_opts = report.fetch_options()?;
+
+    let accessions: Vec<String> = requested.iter().cloned().collect();
+    report.query_label = format!("{} study accession(s)", accessions.len());
+    let fetched = fetch_runs_for_studies(&accessions, &fetch_opts);
+    fetch_opts.log_traffic();
+    let runs = fetched?;
+    if report.stats { print_fetch_stats(&fetch_opts, &report.print_options())?; }
+    let found: BTreeSet<&str> = runs.iter().map(|r| r.study_accession.as_str()).collect();
+    for acc in requested.iter().filter(|a| !found.contains(a.as_str())) {
+        warn!("{}: no Oxford Nanopore runs returned", acc);
+    }
+    report_studies(runs, report, &fetch_opts)?;
+    check_partial(&fetch_opts)
+}
+
+/// `search`: ONT runs whose study title or species mentions the term, unbounded or
+/// within the `--weeks`/`--from`/`--to` release window (a rolling `--weeks` runs to today).
+fn search_studies(args: SearchArgs) -> Result<()> {
+    let SearchArgs { term, weeks, from, to, strict_window, min_date, force, today, mut report } = args;
+    let term = term.trim().to_string();
+    if term.is_empty() || term.chars().all(|c| c == '"' || c == '*') { bail!("search TERM must contain some text"); }
+    let today = resolve_today(today, Utc::now().date_naive())?;
+    let spans = if weeks.is_none() && from.is_empty() && to.is_empty() {
+        Vec::new()
     } else {
-        if to.is_some() { bail!("--to requires --from"); }
-        let since = (Utc::now() - Duration::weeks(weeks)).date_naive();
-        info!("rolling window (released OR updated) since {} ({} weeks)", since, weeks);
-        fetch_runs_since(since)?
+        match resolve_window(weeks.unwrap_or(8), &from, &to, today, strict_window, if force { None } else { Some(min_date) })? {
+            Window::Rolling { since } => vec![(since, today)],
+            Window::Fixed { start, end } => vec![(start, end)],
+            Window::Several(spans) => spans,
+        }
     };
+    if spans.is_empty() && report.resume.is_some() { bail!("--resume checkpoints windowed scans; an unbounded search is a single request (add --weeks or --from)"); }
+    report.resolve()?;
+    let fetch_opts = report.fetch_options()?;
 
-    if runs.is_empty() {
-        println!("No Oxford Nanopore runs found for the selected window.");
-        return Ok(())
+    let bound = if spans.is_empty() { "any release date".to_string() } else { spans.iter().map(|(s, e)| format!("released {} .. {}", s, e)).collect::<Vec<_>>().join(", ") };
+    info!("text search for {:?} ({})", term, bound);
+    report.query_label = format!("studies mentioning \"{}\" ({})", term, bound);
+    let fetched = fetch_runs_matching(&term, &spans, &fetch_opts);
+    fetch_opts.log_traffic();
+    let runs = fetched?;
+    if report.stats { print_fetch_stats(&fetch_opts, &report.print_options())?; }
+    report_studies(runs, report, &fetch_opts)?;
+    check_partial(&fetch_opts)
+}
+
+/// Fail with [`PartialFetch`] when `--keep-partial` skipped any window. Otherwise the
+/// `--resume` checkpoint has been fully used and is removed.
+fn check_partial(fetch_opts: &FetchOptions) -> Result<()> {
+    let failed = fetch_opts.stats.borrow().failed.clone();
+    if !failed.is_empty() { return Err(PartialFetch { failed }.into()); }
+    if let Some(cp) = &fetch_opts.checkpoint { cp.finish().context("remove --resume checkpoint")?; }
+    Ok(())
+}
+
+impl ReportArgs {
+    /// Table styling for stdout (`--max-col-width`, `--color`, `--pretty`); the stats tables use it too.
+    fn print_options(&self) -> PrintOptions {
+        PrintOptions { max_col_width: self.max_col_width.map(|n| n as usize), color: self.color.enabled(), pretty: self.pretty }
     }
 
-    use std::collections::{BTreeMap, BTreeSet};
+    /// Whether output goes through `$PAGER`: `--pager` without `--quiet`, and stdout is a terminal.
+    fn paging(&self) -> bool {
+        use std::io::IsTerminal;
+        self.paging_on(std::io::stdout().is_terminal())
+    }
 
-    #[derive(Default)]
-    struct Agg {
-        plats: BTreeSet<String>,
-        types: BTreeSet<String>,
-        species: BTreeSet<String>,
-        samples: BTreeSet<String>,
-        bases: u128,
-        title: String,
-        release: String,
+    /// [`paging`](Self::paging) for a stdout that is (`is_tty`) or is not a terminal.
+    fn paging_on(&self, is_tty: bool) -> bool {
+        self.pager && !self.quiet && is_tty
     }
 
-    let mut by_study: BTreeMap<String, Agg> = BTreeMap::new();
+    /// Human-readable list of the study/run filters in effect, for the HTML header.
+    fn filter_summary(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        if !self.species.is_empty() { out.push(format!("species: {}", self.species.join(" | "))); }
+        if !self.exclude_species.is_empty() { out.push(format!("excluding species: {}", self.exclude_species.join(" | "))); }
+        if !self.center.is_empty() { out.push(format!("center: {}", self.center.join(" | "))); }
+        if !self.platform.is_empty() { out.push(format!("platform: {}", self.platform.join(" | "))); }
+        if !self.exclude_platform.is_empty() { out.push(format!("excluding platform: {}", self.exclude_platform.join(" | "))); }
+        if let Some(n) = self.min_platforms { out.push(format!("platforms >= {}", n)); }
+        if !self.strategy.is_empty() { out.push(format!("sequencing type: {}", self.strategy.join(" | "))); }
+        if !self.exclude_strategy.is_empty() { out.push(format!("excluding sequencing type: {}", self.exclude_strategy.join(" | "))); }
+        match (self.collected_from, self.collected_to) {
+            (None, None) => {}
+            (f, t) => out.push(format!("collected {} .. {}", f.map_or("*".to_string(), |d| d.to_string()), t.map_or("*".to_string(), |d| d.to_string()))),
+        }
+        if let Some(n) = self.biosamples_min { out.push(format!("biosamples >= {}", n)); }
+        if let Some(n) = self.biosamples_max { out.push(format!("biosamples <= {}", n)); }
+        out
+    }
 
-    for r in &runs {
-        let a = by_study.entry(r.study_accession.clone()).or_default();
-        a.plats.insert(map_platform(r.instrument_model.as_deref()).to_string());
-        if let Some(strat) = r.library_strategy.as_deref() { a.types.insert(map_strategy(strat)); }
-        if let Some(sp) = r.scientific_name.as_deref() { if !sp.is_empty() { a.species.insert(sp.to_string()); } }
-        if let Some(fp) = r.first_public.as_deref() { if a.release.is_empty() || fp < a.release.as_str() { a.release = fp.to_string(); } }
-        if let Some(t) = r.study_title.as_deref() { if !t.is_empty() && a.title.is_empty() { a.title = t.to_string(); } }
-        if let Some(samp) = r.sample_accession.as_deref() { if !samp.is_empty() { a.samples.insert(samp.to_string()); } }
-        if let Some(bc) = r.base_count.as_deref() {
-            if let Ok(v) = bc.parse::<u64>() {
-                a.bases = a.bases.saturating_add(v as u128);
+    /// Check cross-flag constraints and turn `--output-dir` into concrete export paths.
+    fn resolve(&mut self) -> Result<()> {
+        if let (Some(f), Some(t)) = (self.collected_from, self.collected_to) {
+            if t < f { bail!("--collected-to ({}) is before --collected-from ({})", t, f); }
+        }
+        if let (Some(lo), Some(hi)) = (self.biosamples_min, self.biosamples_max) {
+            if hi < lo { bail!("--biosamples-max ({}) is below --biosamples-min ({})", hi, lo); }
+        }
+        if let Some(dir) = self.output_dir.take() {
+            std::fs::create_dir_all(&dir).with_context(|| format!("create output directory {}", dir.display()))?;
+            let stem = self.basename.take().unwrap_or_else(|| Utc::now().format("herring-%Y%m%d").to_string());
+            let wanted = |f: ExportFormat| self.formats.is_empty() || self.formats.contains(&f);
+            let derived = |f: ExportFormat, ext: &str| if wanted(f) { Some(dir.join(format!("{}.{}", stem, ext))) } else { None };
+            let (csv, json, html) = (derived(ExportFormat::Csv, "csv"), derived(ExportFormat::Json, "json"), derived(ExportFormat::Html, "html"));
+            self.csv = self.csv.take().or(csv);
+            self.json = self.json.take().or(json);
+            self.html = self.html.take().or(html);
+        }
+        if self.html_embed_data && self.html.is_none() { warn!("--html-embed-data has no effect without --html"); }
+        if self.fields_preset == FieldsPreset::Minimal {
+            // Without these fields the filters would drop (or lump together) every run.
+            let needs = [
+                ("--collected-from/--collected-to", "collection_date", self.collected_from.is_some() || self.collected_to.is_some()),
+                ("--species/--exclude-species", "scientific_name", !self.species.is_empty() || !self.exclude_species.is_empty()),
+                ("--top-species", "scientific_name", self.top_species.is_some()),
+                ("--center", "center_name", !self.center.is_empty()),
+                ("--platform/--exclude-platform", "instrument_model", !self.platform.is_empty() || !self.exclude_platform.is_empty()),
+                ("--min-platforms", "instrument_model", self.min_platforms.is_some()),
+                ("--drop-unknown-platform", "instrument_model", self.drop_unknown_platform),
+                ("--strict-ont", "instrument_model", self.strict_ont.is_some()),
+                ("--strategy/--exclude-strategy", "library_strategy", !self.strategy.is_empty() || !self.exclude_strategy.is_empty()),
+                ("--biosamples-min/--biosamples-max", "sample_accession", self.biosamples_min.is_some() || self.biosamples_max.is_some()),
+                ("--dedup-bases-by sample", "sample_accession", self.dedup_bases_by == BaseDedup::Sample),
+                ("--group-by", "its key field", self.group_by != GroupBy::Study),
+            ];
+            if let Some((flag, field, _)) = needs.iter().find(|(_, _, set)| *set) {
+                bail!("{} needs {}, which --fields-preset minimal does not fetch; use --fields-preset standard", flag, field);
             }
         }
+        if self.group_by != GroupBy::Study || self.by_week {
+            let by = if self.by_week { "--by-week".to_string() } else { format!("--group-by {}", self.group_by.name()) };
+            if self.html.is_some() { bail!("{} supports stdout, CSV and JSON only; drop --html (or use --formats csv,json)", by); }
+            if self.webhook.is_some() { bail!("{} cannot be combined with --webhook (it posts study rows)", by); }
+            if self.append { bail!("{} cannot be combined with --append", by); }
+            if self.explode_species { bail!("{} cannot be combined with --explode-species (it reshapes study rows)", by); }
+        }
+        if self.compress != Compress::None {
+            let c = self.compress;
+            for p in [&mut self.csv, &mut self.json, &mut self.runs_csv] { *p = p.take().map(|p| c.path(p)); }
+        }
+        Ok(())
+    }
+
+    /// Fetch options derived from these flags; the deadline starts now.
+    fn fetch_options(&self) -> Result<FetchOptions> {
+        let checkpoint = self.resume.as_ref().map(|p| Checkpoint::open(p.clone()).with_context(|| format!("read --resume checkpoint {}", p.display()))).transpose()?;
+        Ok(FetchOptions {
+            deadline: self.deadline_secs.map(|s| Deadline::starting_now(std::time::Duration::from_secs(s))),
+            client: self.client.config(),
+            page_size: self.page_size.map(|n| n as usize),
+            rate_limit: self.rate_limit.map(RateLimit::per_second),
+            require_handshake: self.require_handshake,
+            keep_partial: self.keep_partial,
+            preset: self.fields_preset,
+            checkpoint,
+            ..Default::default()
+        })
+    }
+}
+
+/// Filter, aggregate, print and export fetched runs.
+fn report_studies(runs: Vec<RunRecord>, mut args: ReportArgs, fetch_opts: &FetchOptions) -> Result<()> {
+    if let Some(path) = args.runs_csv.take() { write_runs_csv(&runs, path, args.compress)?; }
+    let runs = filter_collected(runs, args.collected_from, args.collected_to);
+    let runs = match args.strict_ont { Some(mode) => strict_ont(runs, mode), None => runs };
+    let runs = if args.drop_unknown_platform { drop_unknown_platform(runs) } else { runs };
+
+    if runs.is_empty() {
+        if args.fail_on_empty { bail!("no Oxford Nanopore runs found for the selected window"); }
+        outln!("No Oxford Nanopore runs found for the selected window.");
+        return Ok(())
     }
 
-    let mut rows: Vec<Row> = Vec::new();
+    let opts = AggregateOptions {
+        species: SpeciesFilter {
+            include: &args.species, exclude: &args.exclude_species, normalize: args.normalize_species,
+            unknown_label: &args.unknown_species_label, order: args.species_order,
+        },
+        centers: &args.center,
+        platforms: PlatformFilter { include: &args.platform, exclude: &args.exclude_platform, min_count: args.min_platforms.map_or(0, usize::from) },
+        strategies: StrategyFilter { include: &args.strategy, exclude: &args.exclude_strategy },
+        dedup_bases: args.dedup_bases_by,
+        num: NumFormat { human_sizes: args.human_sizes, decimal_comma: args.decimal_comma, precision: args.gigabases_precision as usize },
+        ..Default::default()
+    };
+    let (mut rows, bad_base_counts) = aggregate(&runs, &opts);
+    if args.include_analyses { count_analyses(&mut rows, fetch_opts)?; }
+    report_rows(rows, bad_base_counts, Some(&runs), args, fetch_opts)
+}
+
+/// `--include-analyses`: fetch the `analysis` records of the aggregated studies and
+/// set each row's count (0 when ENA has none).
+fn count_analyses(rows: &mut [Row], fetch_opts: &FetchOptions) -> Result<()> {
+    let accessions: Vec<String> = rows.iter().map(|r| r.acc.clone()).collect();
+    let mut counts: BTreeMap<String, u32> = BTreeMap::new();
+    for a in fetch_analyses_for_studies(&accessions, fetch_opts)? { *counts.entry(a.study_accession).or_default() += 1; }
+    for r in rows.iter_mut() { r.analyses = Some(counts.get(&r.acc).copied().unwrap_or(0)); }
+    Ok(())
+}
+
+/// Row-level filters, then print, export and webhook. `runs` backs `--group-by`
+/// and is `None` when rendering saved rows (`--input`).
+fn report_rows(mut rows: Vec<Row>, bad_base_counts: usize, runs: Option<&[RunRecord]>, args: ReportArgs, fetch_opts: &FetchOptions) -> Result<()> {
+    let num = NumFormat { human_sizes: args.human_sizes, decimal_comma: args.decimal_comma, precision: args.gigabases_precision as usize };
+    let print_opts = args.print_options();
+    let mut meta = HtmlMeta {
+        query: args.query_label.clone(), filters: args.filter_summary(), generated: Utc::now(),
+        unknown_species: args.unknown_species_label.clone(), embedded_json: None, num,
+    };
+    let ReportArgs {
+        group_by, by_week, dedup_bases_by, normalize_species, unknown_species_label, biosamples_min, biosamples_max,
+        csv, append, explode_species, csv_bom, compress, json, compact, json_indent, json_keys, html, html_embed_data,
+        summary, counts_json, top_species, webhook, webhook_strict, fail_on_empty,
+        show_library_details, include_platform_column_raw, show_span, show_sample_titles, fields_preset, no_title, columns,
+        species_link, no_species_links, species_link_base, seen_db, sort_by, ..
+    } = args;
+
+    if let Some(lo) = biosamples_min {
+        let before = rows.len();
+        rows.retain(|r| r.biosamples >= lo);
+        info!("--biosamples-min {} removed {} studies", lo, before - rows.len());
+    }
+    if let Some(hi) = biosamples_max {
+        let before = rows.len();
+        rows.retain(|r| r.biosamples <= hi);
+        info!("--biosamples-max {} removed {} studies", hi, before - rows.len());
+    }
+
+    let indent = if compact { 0 } else { json_indent as usize };
+    // Written before the empty check so a dashboard sees zeros rather than a stale file.
+    if let Some(path) = &counts_json { write_json_file(path, &json_keys.wrap(&Summary::of(&rows, bad_base_counts)), indent, Compress::None)?; }
+    if rows.is_empty() {
+        if fail_on_empty { bail!("no studies matched the selected window and filters"); }
+        outln!("No studies matched the selected window and filters.");
+        return Ok(())
+    }
+
+    let known = seen_db.as_deref().map(read_seen_db).transpose()?;
+    if let Some(known) = &known {
+        for r in rows.iter_mut() { r.seen = Some(known.contains(&r.acc)); }
+    }
+    // After tagging, so `--sort-by seen` has something to sort on.
+    if let Some(col) = sort_by { sort_rows(&mut rows, col); }
+    // Recorded only once everything was printed and written, so a failed export is reported again.
+    let save_seen = || match (&seen_db, &known) {
+        (Some(path), Some(known)) => write_seen_db(path, known.iter().chain(rows.iter().map(|r| &r.acc))),
+        _ => Ok(()),
+    };
+
+    let species = SpeciesFilter { normalize: normalize_species, unknown_label: &unknown_species_label, ..Default::default() };
+    let group_opts = AggregateOptions { species, dedup_bases: dedup_bases_by, group_by, ..Default::default() };
+    if let (GroupBy::Platform | GroupBy::Species | GroupBy::Center, Some(runs)) = (group_by, runs) {
+        let groups = group(runs, &rows, &group_opts);
+        print_df(&groups_df(&groups, group_by.name(), num)?, &print_opts)?;
+        if summary { outln!("{}", Summary::of(&rows, bad_base_counts).text(num)); }
+        if let Some(n) = top_species { print_top_species(runs, &rows, n, &group_opts, num, &print_opts)?; }
+        if let Some(path) = csv { write_groups_csv(&groups, group_by.name(), path, csv_bom, compress, num)?; }
+        if let Some(path) = json { write_groups_json(&groups, group_by.name(), path, indent, json_keys, compress, num)?; }
+        return save_seen()
+    }
+    if by_week {
+        let weeks = week_rows(&rows);
+        print_df(&groups_df(&weeks, "week", num)?, &print_opts)?;
+        if summary { outln!("{}", Summary::of(&rows, bad_base_counts).text(num)); }
+        if let (Some(n), Some(runs)) = (top_species, runs) { print_top_species(runs, &rows, n, &group_opts, num, &print_opts)?; }
+        if let Some(path) = csv { write_groups_csv(&weeks, "week", path, csv_bom, compress, num)?; }
+        if let Some(path) = json { write_groups_json(&weeks, "week", path, indent, json_keys, compress, num)?; }
+        return save_seen()
+    }
+
+    let flags = ColumnFlags {
+        library_details: show_library_details, raw_models: include_platform_column_raw, span: show_span, sample_titles: show_sample_titles,
+        seen: seen_db.is_some(), analyses: rows.iter().any(|r| r.analyses.is_some()), no_title, preset: fields_preset,
+    };
+    let selected = (!columns.is_empty()).then_some(columns.as_slice());
+    let columns = selected.map_or_else(|| table_columns(flags, false), <[Column]>::to_vec);
+    let df = rows_df(&rows, &columns)?;
+
+    print_df(&df, &print_opts)?;
+    if summary { outln!("{}", Summary::of(&rows, bad_base_counts).text(num)); }
+    if let (Some(n), Some(runs)) = (top_species, runs) { print_top_species(runs, &rows, n, &group_opts, num, &print_opts)?; }
 
-    for (acc, a) in by_study.into_iter() {
-        let plat = a.plats.into_iter().collect::<Vec<_>>().join(", ");
-        let seqt = a.types.into_iter().collect::<Vec<_>>().join(", ");
-        let sp = {
-            let mut v: Vec<_> = a.species.into_iter().collect();
-            if v.len() > 5 { v.truncate(5); }
-            v.join(", ")
+    let export_columns = selected.map_or_else(|| table_columns(flags, true), <[Column]>::to_vec);
+    let link = if no_species_links { SpeciesLink::None } else { species_link };
+    let exploded = explode_species.then(|| explode_rows(&rows));
+    if html_embed_data && html.is_some() { meta.embedded_json = Some(serde_json::to_string(&json_keys.wrap(&json_rows(&rows, !no_title, selected)))?); }
+    // Writers target distinct files, so run them side by side and join before the webhook.
+    std::thread::scope(|sc| -> Result<()> {
+        let (rows, cols, base) = (&rows, &export_columns, species_link_base.as_deref());
+        let table = exploded.as_deref().unwrap_or(rows);
+        let mut jobs: Vec<std::thread::ScopedJoinHandle<'_, Result<()>>> = Vec::new();
+        if let Some(path) = csv { jobs.push(sc.spawn(move || write_csv(table, cols, path, append, csv_bom, compress, num.csv_delimiter()))); }
+        if let Some(path) = json { jobs.push(sc.spawn(move || write_json(table, path, indent, json_keys, !no_title, selected, compress))); }
+        if let Some(path) = html { jobs.push(sc.spawn(move || write_html(rows, cols, path, link, base, sort_by.unwrap_or(Column::ReleaseDate), &meta))); }
+        for job in jobs {
+            job.join().map_err(|_| anyhow::anyhow!("export writer thread panicked"))??;
+        }
+        Ok(())
+    })?;
+
+    if let Some(url) = webhook {
+        let problem: Option<anyhow::Error> = match post_json(&url, &json_keys.wrap(&json_rows(&rows, !no_title, selected)), fetch_opts) {
+            Ok(status) if status.is_success() => { outln!("Posted {} rows to webhook ({})", rows.len(), status); None }
+            Ok(status) => Some(EnaError::Http { status, url: url.clone(), what: "webhook POST".into() }.into()),
+            Err(e) => Some(anyhow::Error::new(e).context("webhook POST failed")),
         };
-        let biosamples = a.samples.len() as u32;
-        let gb = (a.bases as f64) / 1e9_f64;
-        let gigabases_num = (gb * 10.0).round() / 10.0; // one decimal
-        let gigabases_str = format!("{:.1}", gigabases_num);
-        rows.push(Row { acc, release: a.release, platform: plat, seq_type: seqt, species: sp, biosamples, gigabases_num, gigabases_str, title: a.title });
-    }
-
-    // DataFrame for stdout (gigabases as formatted string)
-    let acc: Vec<_> = rows.iter().map(|r| r.acc.as_str()).collect();
-    let release: Vec<_> = rows.iter().map(|r| r.release.as_str()).collect();
-    let platform: Vec<_> = rows.iter().map(|r| r.platform.as_str()).collect();
-    let seq_type: Vec<_> = rows.iter().map(|r| r.seq_type.as_str()).collect();
-    let species: Vec<_> = rows.iter().map(|r| r.species.as_str()).collect();
-    let biosamples: Vec<u32> = rows.iter().map(|r| r.biosamples).collect();
-    let gigabases: Vec<_> = rows.iter().map(|r| r.gigabases_str.as_str()).collect();
-    let title: Vec<_> = rows.iter().map(|r| r.title.as_str()).collect();
-
-    let df = df!(
-        "study_accession" => acc,
-        "release_date" => release,
-        "platform" => platform,
-        "sequencing_type" => seq_type,
-        "species" => species,
-        "biosamples" => biosamples,
-        "gigabases" => gigabases,
-        "study_title" => title,
-    )?;
-
-    let df = df.sort(["release_date"], SortMultipleOptions { descending: vec![true], ..Default::default() })?;
-
-    print_df(&df)?;
-
-    if let Some(path) = csv { write_csv(&rows, path)?; }
-    if let Some(path) = json { write_json(&rows, path)?; }
-    if let Some(path) = html { write_html(&rows, path)?; }
+        if let Some(e) = problem {
+            if webhook_strict { return Err(e); }
+            warn!("{:#}", e);
+        }
+    }
+
+    save_seen()
+}
+
+/// `--explode-species`: one copy of each row per entry of its `species_names` (a study
+/// without species keeps its single row). Everything else stays study-level.
+fn explode_rows(rows: &[Row]) -> Vec<Row> {
+    let mut out = Vec::with_capacity(rows.len());
+    for r in rows {
+        if r.species_names.len() <= 1 { out.push(r.clone()); continue; }
+        out.extend(r.species_names.iter().map(|name| Row { species: name.clone(), species_names: vec![name.clone()], ..r.clone() }));
+    }
+    out
+}
+
+/// Rows whose study accession is not in `previous`, preserving order.
+fn new_studies(previous: &BTreeSet<String>, rows: Vec<Row>) -> Vec<Row> {
+    rows.into_iter().filter(|r| !previous.contains(&r.acc)).collect()
+}
+
+/// Start of `watch`'s rolling window for a poll on `today`, validated like `list --weeks`
+/// (at least one week, not before `--min-date` unless `--force`).
+fn watch_since(args: &WatchArgs, today: NaiveDate) -> Result<NaiveDate> {
+    match resolve_window(args.weeks, &[], &[], today, false, if args.force { None } else { Some(args.min_date) })? {
+        Window::Rolling { since } => Ok(since),
+        _ => unreachable!("no --from, so the window is rolling"),
+    }
+}
+
+/// Poll the rolling window until Ctrl-C, printing studies absent from the previous poll.
+///
+/// The first poll only establishes a baseline. Fetch errors are logged and the
+/// next poll proceeds as scheduled. A first Ctrl-C stops after the current
+/// poll/sleep; a second one exits immediately.
+fn watch_studies(args: WatchArgs) -> Result<()> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    // Refuse a bad window before installing the handler and polling.
+    watch_since(&args, Utc::now().date_naive())?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let stop = stop.clone();
+        ctrlc::set_handler(move || {
+            if stop.swap(true, Ordering::SeqCst) { std::process::exit(130); }
+            eprintln!("Stopping after the current poll (Ctrl-C again to exit now)...");
+        }).context("install Ctrl-C handler")?;
+    }
+
+    // clap caps --interval-mins, so this cannot overflow.
+    let interval = std::time::Duration::from_secs(args.interval_mins * 60);
+    let columns = table_columns(ColumnFlags::default(), false);
+    let mut previous: Option<BTreeSet<String>> = None;
+
+    while !stop.load(Ordering::SeqCst) {
+        let now = Utc::now();
+        let since = watch_since(&args, now.date_naive())?;
+        info!("watch poll: rolling window since {} ({} weeks)", since, args.weeks);
+        match fetch_runs_since(since, now.date_naive(), false, &FetchOptions { client: args.client.config(), ..Default::default() }) {
+            Ok(runs) => {
+                let (rows, _) = aggregate(&runs, &AggregateOptions::default());
+                let current: BTreeSet<String> = rows.iter().map(|r| r.acc.clone()).collect();
+                match &previous {
+                    None => println!("[{}] baseline: {} studies", now.format("%Y-%m-%d %H:%M:%SZ"), current.len()),
+                    Some(prev) => {
+                        let fresh = new_studies(prev, rows);
+                        if fresh.is_empty() {
+                            info!("no new studies this poll ({} total)", current.len());
+                        } else {
+                            println!("[{}] {} new studies", now.format("%Y-%m-%d %H:%M:%SZ"), fresh.len());
+                            print_df(&rows_df(&fresh, &columns)?, &PrintOptions::default())?;
+                        }
+                    }
+                }
+                previous = Some(current);
+            }
+            Err(e) => warn!("watch poll failed; retrying at next interval: {:#}", e),
+        }
+
+        let wake = Instant::now() + interval;
+        while !stop.load(Ordering::SeqCst) {
+            let left = wake.saturating_duration_since(Instant::now());
+            if left.is_zero() { break; }
+            std::thread::sleep(left.min(std::time::Duration::from_secs(1)));
+        }
+    }
+    println!("Stopped.");
+    Ok(())
+}
+
+/// Print the client-affecting environment and a PASS/FAIL line per connectivity check.
+///
+/// Fails (exit 1) when any check fails, so it can gate scripted setups.
+fn run_doctor(args: DoctorArgs) -> Result<()> {
+    println!("Environment:");
+    for (k, v) in client_env() {
+        println!("  {:<22} {}", k, v.as_deref().unwrap_or("(unset)"));
+    }
+    println!();
+    let checks = doctor(&FetchOptions { client: args.client.config(), ..Default::default() });
+    let width = checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    for c in &checks {
+        println!("{}  {}  {}", if c.ok { "PASS" } else { "FAIL" }, pad(c.name, width), c.detail);
+    }
+    let failed = checks.iter().filter(|c| !c.ok).count();
+    if failed > 0 { bail!("{} doctor check(s) failed", failed); }
+    println!("All checks passed.");
+    if args.probe { probe(&args)?; }
+    Ok(())
+}
 
+/// `doctor --probe`: the field → status table.
+fn probe(args: &DoctorArgs) -> Result<()> {
+    let fields = probe_field_list(args.fields_preset, &args.probe_field);
+    let probes = probe_fields(&fields, &FetchOptions { client: args.client.config(), ..Default::default() })?;
+    let status = |s: &FieldStatus| match s { FieldStatus::Unknown => "unknown", FieldStatus::Absent => "absent", FieldStatus::Empty => "empty", FieldStatus::Populated(_) => "present" };
+    println!();
+    print_df(&DataFrame::new(vec![
+        Series::new("field".into(), probes.iter().map(|p| p.field.clone()).collect::<Vec<String>>()),
+        Series::new("status".into(), probes.iter().map(|p| status(&p.status)).collect::<Vec<&str>>()),
+        Series::new("sample".into(), probes.iter().map(|p| match &p.status { FieldStatus::Populated(v) => v.clone(), _ => String::new() }).collect::<Vec<String>>()),
+    ])?, &PrintOptions::default())?;
+    println!("present/empty: value or ENA null in one sample ONT run (empty fields may be set on other runs); absent: not returned; unknown: not a read_run field");
+    let unknown = probes.iter().filter(|p| p.status == FieldStatus::Unknown).count();
+    if unknown > 0 { bail!("{} requested field(s) are not read_run fields", unknown); }
     Ok(())
 }
 
 /// Write CSV export with human-formatted `gigabases`.
-fn write_csv(rows: &[Row], path: PathBuf) -> Result<()> {
-    let mut wtr = csv::Writer::from_path(&path)?;
-    wtr.write_record([
-        "study_accession","release_date","platform","sequencing_type","species","biosamples","gigabases","study_title"
-    ])?;
+///
+/// With `append`, an existing non-empty file is extended without a new header
+/// row, after checking that its header matches the columns being written.
+/// `--append` never combines with `compress` (enforced by clap).
+fn write_csv(rows: &[Row], columns: &[Column], path: PathBuf, append: bool, bom: bool, compress: Compress, delimiter: u8) -> Result<()> {
+    let header: Vec<&str> = columns.iter().map(|c| c.name()).collect();
+    let existing = append && std::fs::metadata(&path).map(|m| m.len() > 0).unwrap_or(false);
+    let mut wtr = if existing {
+        let mut rdr = csv::ReaderBuilder::new().delimiter(delimiter).from_path(&path).with_context(|| format!("read existing CSV {}", path.display()))?;
+        let found: Vec<String> = rdr.headers()?.iter().map(str::to_string).collect();
+        if found != header {
+            bail!("cannot append to {}: header {:?} does not match expected {:?}", path.display(), found, header);
+        }
+        let f = std::fs::OpenOptions::new().append(true).open(&path)?;
+        csv::WriterBuilder::new().delimiter(delimiter).from_writer(ExportFile::Plain(std::io::BufWriter::new(f)))
+    } else {
+        let mut wtr = create_csv(&path, bom, compress, delimiter)?;
+        wtr.write_record(&header)?;
+        wtr
+    };
     for r in rows {
-        wtr.write_record([
-            &r.acc, &r.release, &r.platform, &r.seq_type, &r.species,
-            &r.biosamples.to_string(), &r.gigabases_str, &r.title
-        ])?;
+        wtr.write_record(columns.iter().map(|c| c.text(r)))?;
+    }
+    ExportFile::finish_csv(wtr)?;
+    outln!("{} CSV to {}", if existing { "Appended" } else { "Wrote" }, path.display());
+    Ok(())
+}
+
+/// Create (truncate) a CSV file, writing the UTF-8 BOM first under `--csv-bom`.
+fn create_csv(path: &std::path::Path, bom: bool, compress: Compress, delimiter: u8) -> Result<csv::Writer<ExportFile>> {
+    let mut f = compress.create(path)?;
+    if bom { f.write_all(b"\xEF\xBB\xBF")?; }
+    Ok(csv::WriterBuilder::new().delimiter(delimiter).from_writer(f))
+}
+
+/// Write `--group-by`/`--by-week` rows as CSV; the key column is named after the dimension.
+/// Gigabases stay plain (no units) but follow `--decimal-comma`.
+fn write_groups_csv(groups: &[GroupRow], key: &'static str, path: PathBuf, bom: bool, compress: Compress, num: NumFormat) -> Result<()> {
+    let mut wtr = create_csv(&path, bom, compress, num.csv_delimiter())?;
+    wtr.write_record([key, "studies", "biosamples", "runs", "gigabases"])?;
+    for g in groups {
+        wtr.write_record([g.key.clone(), g.studies.to_string(), g.biosamples.to_string(), g.runs.to_string(), num.plain(group_gigabases(g, num))])?;
     }
-    wtr.flush()?;
-    println!("Wrote CSV to {}", path.display());
+    ExportFile::finish_csv(wtr)?;
+    outln!("Wrote CSV to {}", path.display());
+    Ok(())
+}
+
+/// Write `--group-by`/`--by-week` rows as a JSON array of [`OutGroup`].
+fn write_groups_json(groups: &[GroupRow], key: &'static str, path: PathBuf, indent: usize, keys: JsonKeys, compress: Compress, num: NumFormat) -> Result<()> {
+    let out: Vec<OutGroup> = groups.iter().map(|g| OutGroup {
+        group_by: key, key: &g.key, studies: g.studies, biosamples: g.biosamples, runs: g.runs, gigabases: group_gigabases(g, num),
+    }).collect();
+    write_json_file(&path, &keys.wrap(&out), indent, compress)?;
+    outln!("Wrote JSON to {}", path.display());
     Ok(())
 }
 
-/// Write JSON export (machine-friendly, numeric `gigabases`).
-fn write_json(rows: &[Row], path: PathBuf) -> Result<()> {
-    let out: Vec<OutRow> = rows.iter().map(|r| OutRow {
+/// Raw per-run dump for `--runs-csv`: one row per [`RunRecord`], columns in field order.
+fn write_runs_csv(runs: &[RunRecord], path: PathBuf, compress: Compress) -> Result<()> {
+    let mut wtr = csv::Writer::from_writer(compress.create(&path)?);
+    for r in runs { wtr.serialize(r)?; }
+    ExportFile::finish_csv(wtr)?;
+    outln!("Wrote {} runs to {}", runs.len(), path.display());
+    Ok(())
+}
+
+/// One row as a JSON object of the `--columns` selection, keys in column order.
+///
+/// Counts stay numeric; `size` is emitted as bytes.
+struct SelectedRow<'a> {
+    row: &'a Row,
+    columns: &'a [Column],
+}
+
+impl Serialize for SelectedRow<'_> {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut m = s.serialize_map(Some(self.columns.len()))?;
+        for &c in self.columns {
+            match c {
+                Column::Biosamples => m.serialize_entry(c.name(), &self.row.biosamples)?,
+                Column::Analyses => m.serialize_entry(c.name(), &self.row.analyses)?,
+                Column::Gigabases => m.serialize_entry(c.name(), &self.row.gigabases_num)?,
+                Column::Size => m.serialize_entry(c.name(), &self.row.fastq_bytes)?,
+                Column::SampleTitles => m.serialize_entry(c.name(), &self.row.sample_titles)?,
+                _ => m.serialize_entry(c.name(), &c.text(self.row))?,
+            }
+        }
+        m.end()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+/// JSON rows for `--json`/`--webhook`: the documented schema, or a `--columns` selection.
+enum JsonRows<'a> {
+    Fixed(Vec<OutRow<'a>>),
+    Selected(Vec<SelectedRow<'a>>),
+}
+
+/// Pick the JSON row shape for `--json`/`--webhook`.
+fn json_rows<'a>(rows: &'a [Row], title: bool, columns: Option<&'a [Column]>) -> JsonRows<'a> {
+    match columns {
+        Some(columns) => JsonRows::Selected(rows.iter().map(|row| SelectedRow { row, columns }).collect()),
+        None => JsonRows::Fixed(out_rows(rows, title)),
+    }
+}
+
+/// JSON row view of the aggregated rows, shared by `--json` and `--webhook`.
+///
+/// `study_title` is omitted (not emitted as null) when `title` is false.
+fn out_rows(rows: &[Row], title: bool) -> Vec<OutRow<'_>> {
+    rows.iter().map(|r| OutRow {
         study_accession: &r.acc,
         release_date: &r.release,
         platform: &r.platform,
         sequencing_type: &r.seq_type,
+        library_source: &r.lib_source,
+        library_selection: &r.lib_selection,
         species: &r.species,
         biosamples: r.biosamples,
         gigabases: r.gigabases_num,
-        study_title: &r.title,
-    }).collect();
-    let f = File::create(&path)?;
-    serde_json::to_writer_pretty(f, &out)?;
-    println!("Wrote JSON to {}", path.display());
+        fastq_bytes: r.fastq_bytes,
+        study_title: if title { Some(&r.title) } else { None },
+        sample_titles: &r.sample_titles,
+        scientific_names: &r.scientific_names,
+        fastq_ftp: r.fastq.join(";"),
+        seen: r.seen.map(|s| if s { "seen" } else { "new" }),
+        analyses: r.analyses,
+    }).collect()
+}
+
+/// Serialize `value` to `path`: minified when `indent` is 0, else pretty with `indent` spaces.
+fn write_json_file<T: Serialize>(path: &std::path::Path, value: &T, indent: usize, compress: Compress) -> Result<()> {
+    let mut f = compress.create(path)?;
+    if indent == 0 {
+        serde_json::to_writer(&mut f, value)?;
+    } else {
+        let pad = " ".repeat(indent);
+        let mut ser = serde_json::Serializer::with_formatter(&mut f, serde_json::ser::PrettyFormatter::with_indent(pad.as_bytes()));
+        value.serialize(&mut ser)?;
+    }
+    f.finish()
+}
+
+/// Write JSON export (machine-friendly, numeric `gigabases`), pretty (`indent` spaces) or minified (0).
+fn write_json(rows: &[Row], path: PathBuf, indent: usize, keys: JsonKeys, title: bool, columns: Option<&[Column]>, compress: Compress) -> Result<()> {
+    let out = json_rows(rows, title, columns);
+    write_json_file(&path, &keys.wrap(&out), indent, compress)?;
+    outln!("Wrote JSON to {}", path.display());
     Ok(())
 }
 
+/// Human-readable decimal byte size, e.g. `42.3 GB` (1 GB = 10^9 bytes).
+fn format_size(bytes: u128) -> String {
+    const UNITS: [&str; 6] = ["B", "kB", "MB", "GB", "TB", "PB"];
+    let mut v = bytes as f64;
+    let mut unit = 0;
+    while v >= 1000.0 && unit < UNITS.len() - 1 {
+        v /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", v, UNITS[unit]) }
+}
+
 /// Minimal HTML escaping.
 fn escape_html(s: &str) -> String {
 
     s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('\"', "&quot;").replace('\'', "&#39;")
 }
 
-fn wikipedia_search_url(title: &str) -> String {
-    let enc = utf8_percent_encode(title.trim(), NON_ALPHANUMERIC).to_string();
-    format!("https://en.wikipedia.org/w/index.php?search={}", enc)
+/// JSON made safe inside a `<script>` element: `<`, `>` and `&` become `\u` escapes
+/// (still the same JSON), so `</script>` or `<!--` in a title cannot end the block.
+fn escape_script_json(json: &str) -> String {
+    json.replace('<', "\\u003c").replace('>', "\\u003e").replace('&', "\\u0026")
+}
+
+/// Wikipedia search (more forgiving than a direct article link).
+const WIKIPEDIA_SEARCH: &str = "https://en.wikipedia.org/w/index.php?search=";
+/// NCBI Taxonomy name search, used when a species has no `tax_id`.
+const NCBI_TAXONOMY_SEARCH: &str = "https://www.ncbi.nlm.nih.gov/taxonomy/?term=";
+
+/// Link for a species name: `base` followed by the percent-encoded name.
+fn species_url(base: &str, name: &str) -> String {
+    format!("{}{}", base, utf8_percent_encode(name.trim(), NON_ALPHANUMERIC))
+}
+
+/// Href for one species under `link`; a `base` override replaces the name-search prefix.
+///
+/// NCBI prefers a direct taxonomy id link when `tax_id` is known and no override is set.
+fn species_href(link: SpeciesLink, base: Option<&str>, name: &str, tax_id: Option<&str>) -> Option<String> {
+    match (link, base, tax_id) {
+        (SpeciesLink::None, _, _) => None,
+        (SpeciesLink::Ncbi, None, Some(id)) => Some(format!("https://www.ncbi.nlm.nih.gov/Taxonomy/Browser/wwwtax.cgi?id={}", utf8_percent_encode(id, NON_ALPHANUMERIC))),
+        (SpeciesLink::Ncbi, None, None) => Some(species_url(NCBI_TAXONOMY_SEARCH, name)),
+        (_, Some(base), _) => Some(species_url(base, name)),
+        (SpeciesLink::Wikipedia, None, _) => Some(species_url(WIKIPEDIA_SEARCH, name)),
+    }
 }
 
-/// Write a sortable HTML table; ENA accessions + species Wikipedia search links.
-fn write_html(rows: &[Row], path: PathBuf) -> Result<()> {
+/// Render FASTQ locations as a collapsible list of HTTPS links (file count as the summary).
+fn fastq_links(files: &[String]) -> String {
+    if files.is_empty() { return String::new(); }
+    let links = files.iter().map(|f| {
+        let name = f.rsplit('/').next().unwrap_or(f);
+        format!("<a href=\"https://{}\">{}</a>", escape_html(f), escape_html(name))
+    }).collect::<Vec<_>>().join("<br>");
+    format!("<details><summary>{} file{}</summary>{}</details>", files.len(), if files.len() == 1 { "" } else { "s" }, links)
+}
+
+/// Context shown above the HTML table, so a forwarded page explains itself.
+struct HtmlMeta {
+    /// Resolved query (window, accessions, ...); empty when unknown.
+    query: String,
+    filters: Vec<String>,
+    generated: chrono::DateTime<Utc>,
+    /// `--unknown-species-label`, left unlinked in the species column; empty when unset.
+    unknown_species: String,
+    /// `--html-embed-data`: the `--json` rows, serialized.
+    embedded_json: Option<String>,
+    /// Formats the gigabases total like the table's column.
+    num: NumFormat,
+}
+
+impl HtmlMeta {
+    /// `<dl>` block: query, generation time, filters and totals over `rows`.
+    fn render(&self, rows: &[Row]) -> String {
+        let totals = Summary::of(rows, 0);
+        let filters = if self.filters.is_empty() { "none".to_string() } else { self.filters.join("; ") };
+        let mut out = String::from("<dl class=\"meta\">\n");
+        if !self.query.is_empty() { out.push_str(&format!("<dt>Query</dt><dd>{}</dd>\n", escape_html(&self.query))); }
+        out.push_str(&format!("<dt>Generated</dt><dd>{}</dd>\n", self.generated.format("%Y-%m-%d %H:%M UTC")));
+        out.push_str(&format!("<dt>Filters</dt><dd>{}</dd>\n", escape_html(&filters)));
+        out.push_str(&format!("<dt>Totals</dt><dd>{} studies, {}</dd>\n", totals.studies, escape_html(&totals.gigabases_text(self.num))));
+        out.push_str("</dl>\n");
+        out
+    }
+}
+
+/// Write a sortable HTML table; ENA accessions + species links.
+///
+/// Species are linked per [`species_href`], or plain text for [`SpeciesLink::None`].
+fn write_html(rows: &[Row], columns: &[Column], path: PathBuf, species_link: SpeciesLink, species_link_base: Option<&str>, sorted_by: Column, meta: &HtmlMeta) -> Result<()> {
     let mut f = File::create(&path)?;
     let mut html = String::new();
     html.push_str("<!doctype html><meta charset=\"utf-8\"><title>herring results</title>\n");
-    html.push_str("<style>body{font:14px system-ui, sans-serif;padding:16px} table{border-collapse:collapse;width:100%} th,td{border:1px solid #ddd;padding:6px 8px} th{cursor:pointer;background:#f6f6f6;position:sticky;top:0} tr:nth-child(even){background:#fafafa} a{color:#0645ad;text-decoration:none}</style>\n");
+    html.push_str(concat!(
+        "<style>body{font:14px system-ui, sans-serif;padding:16px} ",
+        "dl.meta{display:grid;grid-template-columns:max-content auto;gap:2px 12px;color:#444} dl.meta dt{font-weight:600} ",
+        "table{border-collapse:collapse;width:100%} th,td{border:1px solid #ddd;padding:6px 8px} ",
+        "th{cursor:pointer;background:#f6f6f6;position:sticky;top:0} tr:nth-child(even){background:#fafafa} ",
+        "a{color:#0645ad;text-decoration:none}</style>\n",
+    ));
     html.push_str("<h1>herring results</h1>\n");
-    html.push_str("<p>Click a column header to sort. Default sort is by date (newest first).</p>\n");
+    html.push_str(&meta.render(rows));
+    // Rows arrive in `sorted_by` order; say so, and mark that header like a click would.
+    let ascending = sorted_by.kind() == "str";
+    let order = match sorted_by.kind() { "date" => "newest first", "num" => "largest first", _ => "A-Z" };
+    html.push_str(&format!("<p>Click a column header to sort. Initial order: {} ({}).</p>\n", sorted_by.name().replace('_', " "), order));
     html.push_str("<table id=\"t\"><thead><tr>\n");
-    let headers = [
-        ("study_accession","str"),("release_date","date"),("platform","str"),("sequencing_type","str"),("species","str"),("biosamples","num"),("gigabases","num"),("study_title","str")
-    ];
-    for (h, ty) in headers.iter() {
-        html.push_str(&format!("<th data-type=\"{}\">{}</th>", ty, h.replace('_'," ")));
+    for &c in columns {
+        let (aria, arrow) = match (c == sorted_by, ascending) {
+            (false, _) => (String::new(), ""),
+            (true, true) => (" aria-sort=\"ascending\"".to_string(), " ▲"),
+            (true, false) => (" aria-sort=\"descending\"".to_string(), " ▼"),
+        };
+        html.push_str(&format!("<th data-type=\"{}\"{}>{}<span class=\"arrow\">{}</span></th>", c.kind(), aria, c.name().replace('_'," "), arrow));
     }
     html.push_str("</tr></thead><tbody>\n");
     for r in rows {
-        let url = format!("https://www.ebi.ac.uk/ena/browser/view/{}", r.acc);
         html.push_str("<tr>");
-        html.push_str(&format!("<td><a href=\"{}\" target=\"_blank\" rel=\"noopener\">{}</a></td>", url, escape_html(&r.acc)));
-        html.push_str(&format!("<td>{}</td>", escape_html(&r.release)));
-        html.push_str(&format!("<td>{}</td>", escape_html(&r.platform)));
-        html.push_str(&format!("<td>{}</td>", escape_html(&r.seq_type)));
-        let species_links = if r.species.trim().is_empty() { String::new() } else { r.species.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).map(|s| format!("<a href=\"{}\" target=\"_blank\" rel=\"noopener\">{}</a>", wikipedia_search_url(s), escape_html(s))).collect::<Vec<_>>().join(", ") };
-        html.push_str(&format!("<td>{}</td>", species_links));
-        html.push_str(&format!("<td data-v=\"{}\">{}</td>", r.biosamples, r.biosamples));
-        html.push_str(&format!("<td data-v=\"{}\">{}</td>", r.gigabases_num, r.gigabases_str));
-        html.push_str(&format!("<td>{}</td>", escape_html(&r.title)));
+        for c in columns {
+            let cell = match c {
+                Column::StudyAccession => {
+                    let url = format!("https://www.ebi.ac.uk/ena/browser/view/{}", r.acc);
+                    format!("<td><a href=\"{}\" target=\"_blank\" rel=\"noopener\">{}</a></td>", url, escape_html(&r.acc))
+                }
+                Column::Species => {
+                    // The --unknown-species-label is not a name, so it stays unlinked.
+                    let href = |s: &str| if s == meta.unknown_species { None } else { species_href(species_link, species_link_base, s, r.tax_ids.get(s).map(String::as_str)) };
+                    let species_links = r.species.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).map(|s| match href(s) {
+                        Some(url) => format!("<a href=\"{}\" target=\"_blank\" rel=\"noopener\">{}</a>", escape_html(&url), escape_html(s)),
+                        None => escape_html(s),
+                    }).collect::<Vec<_>>().join(", ");
+                    format!("<td>{}</td>", species_links)
+                }
+                // Sort on the unrounded value so display precision cannot create ties.
+                Column::Gigabases => format!("<td data-v=\"{}\">{}</td>", r.gigabases_num.map_or_else(String::new, |_| (r.bases as f64 / 1e9).to_string()), r.gigabases_str),
+                Column::Size => format!("<td data-v=\"{}\">{}</td>", r.fastq_bytes, format_size(r.fastq_bytes)),
+                Column::FastqFtp => format!("<td data-v=\"{}\">{}</td>", r.fastq.len(), fastq_links(&r.fastq)),
+                // Every other number sorts on data-v; an empty one (no data) sorts last.
+                _ if c.kind() == "num" => format!("<td data-v=\"{}\">{}</td>", c.number(r).map_or_else(String::new, |v| v.to_string()), escape_html(&c.text(r))),
+                _ => format!("<td>{}</td>", escape_html(&c.text(r))),
+            };
+            html.push_str(&cell);
+        }
         html.push_str("</tr>\n");
     }
     html.push_str("</tbody></table>\n");
+    if let Some(json) = &meta.embedded_json {
+        let name = path.file_stem().map_or_else(|| "herring".into(), |s| s.to_string_lossy()).to_string();
+        html.push_str(&format!("<p><button id=\"dl\" data-name=\"{}.json\">Download JSON</button></p>\n", escape_html(&name)));
+        html.push_str(&format!("<script type=\"application/json\" id=\"herring-data\">{}</script>\n", escape_script_json(json)));
+        html.push_str(r#"<script>
+document.getElementById('dl').addEventListener('click',(e)=>{
+  const text=JSON.stringify(JSON.parse(document.getElementById('herring-data').textContent),null,2);
+  const a=document.createElement('a');
+  a.href=URL.createObjectURL(new Blob([text],{type:'application/json'}));
+  a.download=e.target.getAttribute('data-name');
+  a.click();
+  URL.revokeObjectURL(a.href);
+});
+</script>
+"#);
+    }
     html.push_str(r#"<script>
 (function(){
   const tbl=document.getElementById('t');
@@ -295,21 +2327,33 @@ fn write_html(rows: &[Row], path: PathBuf) -> Result<()> {
     if(v!==null) return parseFloat(v);
     return td.textContent.trim();
   };
+  // Blank cells: NaN for numbers (empty data-v), '' for text and dates.
+  const blank=(v,ty)=>ty==='num'?(v===''||isNaN(v)):v==='';
   const cmp=(a,b,ty)=>{
     if(ty==='num') return a-b;
     if(ty==='date') return (a>b)-(a<b);
     return a.localeCompare(b);
   };
+  const mark=(th,asc)=>{
+    tbl.querySelectorAll('th').forEach(h=>{h.removeAttribute('aria-sort');h.querySelector('.arrow').textContent='';});
+    th.setAttribute('aria-sort',asc?'ascending':'descending');
+    th.querySelector('.arrow').textContent=asc?' ▲':' ▼';
+  };
   tbl.querySelectorAll('th').forEach((th,i)=>{
-    let asc=false;
+    // The pre-sorted column flips on its first click; the others start descending.
+    let asc=th.getAttribute('aria-sort')==='descending';
     th.addEventListener('click',()=>{
       const ty=th.getAttribute('data-type');
       const rows=[...tbl.tBodies[0].rows];
       rows.sort((r1,r2)=>{
         const a=get(r1.cells[i]);
         const b=get(r2.cells[i]);
+        // Blank cells (no data reported) group at the bottom in either direction.
+        const ea=blank(a,ty), eb=blank(b,ty);
+        if(ea||eb) return ea-eb;
         return (asc?1:-1)*cmp(a,b,ty);
       });
+      mark(th,asc);
       asc=!asc;
       rows.forEach(r=>tbl.tBodies[0].appendChild(r));
     });
@@ -318,50 +2362,518 @@ fn write_html(rows: &[Row], path: PathBuf) -> Result<()> {
 </script>"#);
 
     f.write_all(html.as_bytes())?;
-    println!("Wrote HTML to {}", path.display());
+    outln!("Wrote HTML to {}", path.display());
     Ok(())
 }
 
-/// Right-pad with spaces to width, measured in `chars()`.
+/// Right-pad with spaces to width, measured in terminal columns ([`display_width`]).
 fn pad(s: &str, width: usize) -> String {
-    let len = s.chars().count();
+    let len = display_width(s);
     if len >= width { s.to_string() } else { format!("{s}{:>width$}", "", width = width - len) }
 }
 
-/// Print a simple monospace table to stdout.
-fn print_df(df: &DataFrame) -> Result<()> {
+/// Left-pad with spaces to width (right-align), measured in terminal columns ([`display_width`]).
+fn pad_left(s: &str, width: usize) -> String {
+    let len = display_width(s);
+    if len >= width { s.to_string() } else { format!("{:>width$}{s}", "", width = width - len) }
+}
+
+/// Terminal columns taken by `s`: East Asian wide/fullwidth characters and most emoji
+/// take two, combining marks and zero-width characters none.
+fn display_width(s: &str) -> usize {
+    s.chars().map(|c| match c as u32 {
+        0x0300..=0x036F | 0x200B..=0x200F | 0xFE00..=0xFE0F => 0,
+        0x1100..=0x115F | 0x2E80..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60 | 0xFFE0..=0xFFE6 | 0x1F300..=0x1F64F | 0x1F900..=0x1F9FF | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }).sum()
+}
+
+/// Truncate to at most `max` display columns, replacing the tail with `…` when cut.
+fn truncate_width(s: &str, max: usize) -> String {
+    if display_width(s) <= max { return s.to_string(); }
+    let (mut out, mut used) = (String::new(), 0);
+    for c in s.chars() {
+        let w = display_width(c.encode_utf8(&mut [0u8; 4]));
+        if used + w > max.saturating_sub(1) { break; }
+        out.push(c);
+        used += w;
+    }
+    out.push('…');
+    out
+}
+
+#[derive(Debug, Default, Clone)]
+/// Rendering knobs for [`print_df`].
+struct PrintOptions {
+    /// Truncate cells to this many display columns (headers are never truncated).
+    max_col_width: Option<usize>,
+    /// Bold header, dimmed separator and every other row dimmed (ANSI).
+    color: bool,
+    /// Box-drawing borders, fitted to the terminal width (`--pretty`).
+    pretty: bool,
+}
+
+/// Print a simple monospace table to stdout (or the `--pager` session, see [`paged`]).
+///
+/// Numeric columns (numeric dtype, or a known numeric [`Column`] carried as
+/// formatted text, like `gigabases`) are right-aligned; everything else is left-aligned.
+/// Widths are display columns, so wide (CJK) names stay aligned.
+fn print_df(df: &DataFrame, opts: &PrintOptions) -> Result<()> {
     let cols = df.get_columns();
+    let mut lines: Vec<String> = Vec::new();
     let names: Vec<String> = df.get_column_names_owned().into_iter().map(|n| n.to_string()).collect();
     let nrows = df.height();
 
-    fn cell_as_str(s: &Series, r: usize) -> String {
-        match s.get(r) {
+    let cell_as_str = |s: &Series, r: usize| -> String {
+        let text = match s.get(r) {
             Ok(AnyValue::Null) => "".to_string(),
-            Ok(v) => v.to_string(),
+            Ok(v) => v.get_str().map(str::to_string).unwrap_or_else(|| v.to_string()),
             Err(_) => "".to_string(),
-        }
-    }
+        };
+        match opts.max_col_width { Some(n) => truncate_width(&text, n), None => text }
+    };
 
-    let mut widths: Vec<usize> = names.iter().map(|n| n.chars().count()).collect();
+    let mut widths: Vec<usize> = names.iter().map(|n| display_width(n)).collect();
     for (i, s) in cols.iter().enumerate() {
         for r in 0..nrows {
             let text = cell_as_str(s, r);
-            let len = text.chars().count();
+            let len = display_width(&text);
             if len > widths[i] { widths[i] = len; }
         }
     }
 
-    let header = names.iter().enumerate().map(|(i, n)| pad(n, widths[i])).collect::<Vec<_>>().join(" | ");
-    println!("{}", header);
-    let sep = widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("-+-");
-    println!("{}", sep);
+    let right: Vec<bool> = cols.iter().zip(&names).map(|(s, n)| s.dtype().is_numeric() || Column::by_name(n).is_some_and(|c| c.kind() == "num")).collect();
+    if opts.pretty { fit_widths(&mut widths, &names, &right); }
+    // Under --pretty a shrunk column cuts its cells; otherwise widths already fit.
+    let fit = |i: usize, text: String| if display_width(&text) > widths[i] { truncate_width(&text, widths[i]) } else { text };
+    let align = |i: usize, text: &str| if right[i] { pad_left(text, widths[i]) } else { pad(text, widths[i]) };
+    let (bar, edge) = if opts.pretty { (" │ ", ["│ ", " │"]) } else { (" | ", ["", ""]) };
+    let rule = |[l, m, r]: [&str; 3], fill: &str| format!("{}{}{}", l, widths.iter().map(|w| fill.repeat(*w)).collect::<Vec<_>>().join(m), r);
+
+    // Styles wrap already-padded text, so escape bytes never affect the width math.
+    let paint = |style: anstyle::Style, text: String| if opts.color { format!("{}{}{}", style.render(), text, style.render_reset()) } else { text };
+    if opts.pretty { lines.push(paint(anstyle::Style::new().dimmed(), rule(["┌─", "─┬─", "─┐"], "─"))); }
+    let header = names.iter().enumerate().map(|(i, n)| align(i, n)).collect::<Vec<_>>().join(bar);
+    lines.push(paint(anstyle::Style::new().bold(), format!("{}{}{}", edge[0], header, edge[1])));
+    let sep = if opts.pretty { rule(["├─", "─┼─", "─┤"], "─") } else { rule(["", "-+-", ""], "-") };
+    lines.push(paint(anstyle::Style::new().dimmed(), sep));
 
     for r in 0..nrows {
         let row = cols.iter().enumerate().map(|(i, s)| {
-            let text = cell_as_str(s, r);
-            pad(&text, widths[i])
-        }).collect::<Vec<_>>().join(" | ");
-        println!("{}", row);
+            let text = fit(i, cell_as_str(s, r));
+            align(i, &text)
+        }).collect::<Vec<_>>().join(bar);
+        let style = if r % 2 == 1 { anstyle::Style::new().dimmed() } else { anstyle::Style::new() };
+        lines.push(paint(style, format!("{}{}{}", edge[0], row, edge[1])));
     }
+    if opts.pretty { lines.push(paint(anstyle::Style::new().dimmed(), rule(["└─", "─┴─", "─┘"], "─"))); }
+    lines.push(String::new());
+    emit(&lines.join("\n"));
     Ok(())
 }
+
+/// Report output held back for one `--pager` session; `None` while output goes straight to stdout.
+static PAGED: std::sync::Mutex<Option<String>> = std::sync::Mutex::new(None);
+
+/// Print `text` to stdout, or append it to the open `--pager` session.
+fn emit(text: &str) {
+    match PAGED.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        Some(buf) => buf.push_str(text),
+        None => print!("{}", text),
+    }
+}
+
+/// Run a report command; with `pager`, everything it prints (tables, `--top-species`,
+/// `--stats`, summaries) is collected and shown in a single [`page`] session at the end.
+fn paged(pager: bool, f: impl FnOnce() -> Result<()>) -> Result<()> {
+    if !pager { return f(); }
+    *PAGED.lock().unwrap_or_else(|e| e.into_inner()) = Some(String::new());
+    let result = f();
+    let text = PAGED.lock().unwrap_or_else(|e| e.into_inner()).take().unwrap_or_default();
+    // Show what was printed before a failure too; the command's own error wins.
+    let shown = if text.is_empty() { Ok(()) } else { page(&text) };
+    result.and(shown)
+}
+
+/// Write `text` to `$PAGER` (split on whitespace; default `less -FRS`, which exits at once
+/// when the table fits and keeps colors), falling back to plain stdout if it cannot start.
+fn page(text: &str) -> Result<()> {
+    let cmd = std::env::var("PAGER").ok().filter(|p| !p.trim().is_empty()).unwrap_or_else(|| "less -FRS".to_string());
+    let mut parts = cmd.split_whitespace();
+    let prog = parts.next().unwrap_or("less");
+    let mut child = match std::process::Command::new(prog).args(parts).stdin(std::process::Stdio::piped()).spawn() {
+        Ok(c) => c,
+        Err(e) => { warn!("cannot start pager {:?} ({}); printing directly", cmd, e); print!("{}", text); return Ok(()) }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // Quitting the pager early closes the pipe; that is not an error.
+        if let Err(e) = stdin.write_all(text.as_bytes()) { if e.kind() != std::io::ErrorKind::BrokenPipe { return Err(e.into()) } }
+    }
+    child.wait().with_context(|| format!("wait for pager {:?}", cmd))?;
+    Ok(())
+}
+
+/// Shrink the widest text columns, one terminal column at a time, until a bordered table fits
+/// `$COLUMNS` (unset or unparseable: leave as is). Widths are [`display_width`]s. Numeric columns
+/// keep their width and no column goes below its header or 8 columns, so a very narrow terminal still wraps.
+fn fit_widths(widths: &mut [usize], names: &[String], right: &[bool]) {
+    let Some(term) = std::env::var("COLUMNS").ok().and_then(|v| v.parse::<usize>().ok()) else { return };
+    let floor = |i: usize| display_width(&names[i]).max(8);
+    // "│ " + cells joined by " │ " + " │"
+    let mut total = widths.iter().sum::<usize>() + 3 * widths.len() + 1;
+    while total > term {
+        let Some(i) = (0..widths.len()).filter(|&i| !right[i] && widths[i] > floor(i)).max_by_key(|&i| widths[i]) else { break };
+        widths[i] -= 1;
+        total -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A run from the JSON fields ENA would return; absent fields are `None`.
+    fn run(v: serde_json::Value) -> RunRecord {
+        serde_json::from_value(v).unwrap()
+    }
+
+    /// One MinION WGS run per `(study, first_public)`.
+    fn study_rows(studies: &[(&str, &str)]) -> Vec<Row> {
+        let runs: Vec<RunRecord> = studies.iter().map(|(acc, date)| run(serde_json::json!({
+            "study_accession": acc, "first_public": date, "instrument_model": "MinION", "base_count": "1000000000", "library_strategy": "WGS",
+        }))).collect();
+        aggregate(&runs, &AggregateOptions::default()).0
+    }
+
+    /// A per-test file under the system temp dir, removed first if a previous run left it.
+    fn temp_path(name: &str) -> PathBuf {
+        let p = std::env::temp_dir().join(format!("herring-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_file(&p);
+        p
+    }
+
+    #[test]
+    fn tied_release_dates_give_identical_csv() {
+        let mut studies = vec![("PRJEB3", "2026-01-01"), ("PRJEB1", "2026-01-01"), ("PRJEB2", "2026-01-01"), ("PRJEB4", "2026-01-02")];
+        let columns = table_columns(ColumnFlags::default(), true);
+        let mut outputs = Vec::new();
+        for name in ["tie-a.csv", "tie-b.csv"] {
+            let path = temp_path(name);
+            write_csv(&study_rows(&studies), &columns, path.clone(), false, false, Compress::None, b',').unwrap();
+            outputs.push(std::fs::read(&path).unwrap());
+            std::fs::remove_file(&path).unwrap();
+            // ENA gives no order guarantee; the second pass sees the runs the other way round.
+            studies.reverse();
+        }
+        assert_eq!(outputs[0], outputs[1]);
+        let text = String::from_utf8(outputs.remove(0)).unwrap();
+        let accs: Vec<&str> = text.lines().skip(1).filter_map(|l| l.split(',').next()).collect();
+        assert_eq!(accs, ["PRJEB4", "PRJEB1", "PRJEB2", "PRJEB3"]);
+    }
+
+    fn ymd(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn resolve_window_rejects_invalid_combinations() {
+        let today = ymd("2026-06-15");
+        let floor = Some(ymd("2020-01-01"));
+        // weeks, --from, --to, --strict-window, expected error
+        type Case<'a> = (i64, &'a [&'a str], &'a [&'a str], bool, &'a str);
+        let cases: [Case; 11] = [
+            (0, &[], &[], false, "--weeks must be at least 1, got 0"),
+            (-2, &["2026-01-01"], &[], false, "--weeks must be at least 1, got -2"),
+            (4, &[], &["2026-02-01"], false, "--to 2026-02-01 requires --from"),
+            (4, &["2026-01-01", "2026-03-01"], &["2026-02-01"], false, "--to given 1 time(s) for 2 --from"),
+            (4, &["2026-03-01"], &["2026-02-01"], false, "--to (2026-02-01) is before --from (2026-03-01)"),
+            (4, &["01/03/2026"], &[], false, "--from must be YYYY-MM-DD, got: 01/03/2026"),
+            (4, &["2026-01-01"], &["2026-02-30"], false, "--to must be YYYY-MM-DD, got: 2026-02-30"),
+            (4, &["2027-01-01"], &[], true, "window 2027-01-01 .. 2027-01-28 starts in the future (today is 2026-06-15 UTC)"),
+            (4, &["2026-06-01"], &[], true, "window 2026-06-01 .. 2026-06-28 ends in the future (today is 2026-06-15 UTC)"),
+            // Past chrono's TimeDelta range: an error, not a panic.
+            (9_999_999_999_999_999, &[], &[], false, "--weeks 9999999999999999 reaches before the earliest representable date"),
+            (9_999_999_999_999_999, &["2026-01-01"], &[], false, "--from 2026-01-01 plus --weeks 9999999999999999 overflows the calendar"),
+        ];
+        for (weeks, from, to, strict, msg) in cases {
+            let err = resolve_window(weeks, &strings(from), &strings(to), today, strict, floor).unwrap_err().to_string();
+            assert!(err.contains(msg), "{:?} / {:?}: {}", from, to, err);
+        }
+        let err = resolve_window(400, &[], &[], today, false, floor).unwrap_err().to_string();
+        assert!(err.starts_with("computed window start 2018-10-15 is before --min-date 2020-01-01"), "{}", err);
+        let err = resolve_window(1, &strings(&["2019-12-31"]), &[], today, false, floor).unwrap_err().to_string();
+        assert!(err.starts_with("computed window start 2019-12-31 is before --min-date"), "{}", err);
+        let err = resolve_window(20_000_000, &[], &[], today, false, None).unwrap_err().to_string();
+        assert!(err.contains("reaches before the earliest representable date"), "{}", err);
+    }
+
+    #[test]
+    fn compressed_exports_round_trip() {
+        use std::io::Read;
+        let rows = study_rows(&[("PRJEB1", "2026-01-02"), ("PRJEB2", "2026-01-01")]);
+        let columns = table_columns(ColumnFlags::default(), true);
+        let write = |name: &str, compress: Compress| -> (Vec<u8>, Vec<u8>) {
+            let (csv_path, json_path) = (temp_path(&format!("{}.csv", name)), temp_path(&format!("{}.json", name)));
+            write_csv(&rows, &columns, csv_path.clone(), false, false, compress, b',').unwrap();
+            write_json(&rows, json_path.clone(), 2, JsonKeys::Snake, true, None, compress).unwrap();
+            let out = (std::fs::read(&csv_path).unwrap(), std::fs::read(&json_path).unwrap());
+            std::fs::remove_file(csv_path).unwrap();
+            std::fs::remove_file(json_path).unwrap();
+            out
+        };
+        let gunzip = |b: Vec<u8>| { let mut out = Vec::new(); flate2::read::GzDecoder::new(b.as_slice()).read_to_end(&mut out).unwrap(); out };
+        let (csv, json) = write("plain", Compress::None);
+        assert!(csv.starts_with(b"study_accession,"));
+        let (gz_csv, gz_json) = write("gzip", Compress::Gzip);
+        assert!(gz_csv.starts_with(&[0x1f, 0x8b]));
+        assert_eq!(gunzip(gz_csv), csv);
+        assert_eq!(gunzip(gz_json), json);
+        let (zst_csv, zst_json) = write("zstd", Compress::Zstd);
+        assert!(zst_csv.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]));
+        assert_eq!(zstd::decode_all(zst_csv.as_slice()).unwrap(), csv);
+        assert_eq!(zstd::decode_all(zst_json.as_slice()).unwrap(), json);
+    }
+
+    /// Top-level keys of the first JSON row, in order.
+    fn first_row_keys(v: &serde_json::Value) -> Vec<String> {
+        v[0].as_object().unwrap().keys().cloned().collect()
+    }
+
+    #[test]
+    fn json_keys_snake_and_camel() {
+        let rows = study_rows(&[("PRJEB1", "2026-01-02")]);
+        let snake = serde_json::to_value(JsonKeys::Snake.wrap(&json_rows(&rows, true, None))).unwrap();
+        let camel = serde_json::to_value(JsonKeys::Camel.wrap(&json_rows(&rows, true, None))).unwrap();
+        let (snake_keys, camel_keys) = (first_row_keys(&snake), first_row_keys(&camel));
+        assert_eq!(&snake_keys[..3], ["study_accession", "release_date", "platform"]);
+        assert!(snake_keys.contains(&"sequencing_type".to_string()));
+        assert_eq!(&camel_keys[..3], ["studyAccession", "releaseDate", "platform"]);
+        assert!(camel_keys.contains(&"sequencingType".to_string()));
+        assert!(camel_keys.iter().all(|k| !k.contains('_')), "{:?}", camel_keys);
+        // Same order and values, and `--input` folds the camel keys back.
+        assert_eq!(camel_keys.iter().map(|k| snake_case(k)).collect::<Vec<_>>(), snake_keys);
+        assert_eq!(camel[0]["studyAccession"], snake[0]["study_accession"]);
+        assert_eq!(rename_keys(camel, snake_case), snake);
+    }
+
+    #[test]
+    fn windows_follow_a_pinned_today() {
+        let now = ymd("2026-10-17");
+        assert_eq!(resolve_today(None, now).unwrap(), now);
+        let today = resolve_today(Some(ymd("2026-03-04")), now).unwrap();
+        assert_eq!(today, ymd("2026-03-04"));
+        let err = resolve_today(Some(ymd("2026-10-18")), now).unwrap_err().to_string();
+        assert_eq!(err, "--today 2026-10-18 is in the future (today is 2026-10-17 UTC)");
+
+        assert_eq!(resolve_window(2, &[], &[], today, false, None).unwrap(), Window::Rolling { since: ymd("2026-02-18") });
+        assert_eq!(resolve_window(1, &strings(&["2026-02-01"]), &[], today, true, None).unwrap(), Window::Fixed { start: ymd("2026-02-01"), end: ymd("2026-02-07") });
+        // Past for the real clock, but in the future of the pinned day.
+        let err = resolve_window(1, &strings(&["2026-03-01"]), &[], today, true, None).unwrap_err().to_string();
+        assert!(err.starts_with("window 2026-03-01 .. 2026-03-07 ends in the future (today is 2026-03-04 UTC)"), "{}", err);
+        assert_eq!(resolve_window(1, &strings(&["2026-03-01"]), &[], now, true, None).unwrap(), Window::Fixed { start: ymd("2026-03-01"), end: ymd("2026-03-07") });
+    }
+
+    /// One MinION run per `(study, base_count)`, all released the same day.
+    fn sized_rows(studies: &[(&str, &str)]) -> Vec<Row> {
+        let runs: Vec<RunRecord> = studies.iter().map(|(acc, bases)| run(serde_json::json!({
+            "study_accession": acc, "first_public": "2026-01-01", "instrument_model": "MinION", "base_count": bases,
+        }))).collect();
+        aggregate(&runs, &AggregateOptions::default()).0
+    }
+
+    #[test]
+    fn counts_keep_unrounded_gigabases() {
+        let rows = sized_rows(&[("PRJEB1", "1234567890"), ("PRJEB2", "10000")]);
+        let counts = serde_json::to_value(Summary::of(&rows, 1)).unwrap();
+        assert_eq!(counts, serde_json::json!({ "studies": 2, "runs": 2, "biosamples": 0, "gigabases": 1.23457789, "unparseable_base_counts": 1 }));
+    }
+
+    #[test]
+    fn summary_line_follows_gigabases_format() {
+        let totals = Summary::of(&sized_rows(&[("PRJEB1", "1234567890"), ("PRJEB2", "10000")]), 0);
+        let line = |precision: usize, human_sizes: bool| totals.text(NumFormat { human_sizes, decimal_comma: false, precision });
+        assert_eq!(line(1, false), "Summary: 2 studies, 2 runs, 0 biosamples, 1.2 Gb; 0 unparseable base_count value(s)");
+        assert!(line(3, false).contains(", 1.235 Gb;"));
+        assert!(line(0, false).contains(", 1 Gb;"));
+        assert!(line(3, true).contains(", 1.2 Gb;"));
+        let big = Summary::of(&sized_rows(&[("PRJEB1", "12345678900000")]), 0);
+        assert!(big.text(NumFormat { human_sizes: true, ..NumFormat::default() }).contains(", 12.3 Tb;"));
+    }
+
+    fn html_meta(num: NumFormat) -> HtmlMeta {
+        HtmlMeta { query: String::new(), filters: Vec::new(), generated: Utc::now(), unknown_species: String::new(), embedded_json: None, num }
+    }
+
+    #[test]
+    fn html_totals_match_the_table() {
+        let rows = sized_rows(&[("PRJEB1", "1234567890"), ("PRJEB2", "10000")]);
+        let totals = |num: NumFormat| html_meta(num).render(&rows).lines().find(|l| l.starts_with("<dt>Totals")).unwrap().to_string();
+        assert_eq!(totals(NumFormat::default()), "<dt>Totals</dt><dd>2 studies, 1.2 Gb</dd>");
+        assert_eq!(totals(NumFormat { precision: 3, ..NumFormat::default() }), "<dt>Totals</dt><dd>2 studies, 1.235 Gb</dd>");
+        let big = sized_rows(&[("PRJEB1", "12345678900000")]);
+        assert!(html_meta(NumFormat { human_sizes: true, ..NumFormat::default() }).render(&big).contains("<dd>1 studies, 12.3 Tb</dd>"));
+    }
+
+    #[test]
+    fn decimal_comma_totals_match_the_rows() {
+        let rows = sized_rows(&[("PRJEB1", "1234567890000"), ("PRJEB2", "500000000")]);
+        let comma = NumFormat { decimal_comma: true, ..NumFormat::default() };
+        assert_eq!(comma.gigabases(rows[0].gigabases_num, rows[0].bases), "1234,6");
+        assert!(Summary::of(&rows, 0).text(comma).contains(", 1235,1 Gb;"));
+        assert!(html_meta(comma).render(&rows).contains("<dd>2 studies, 1235,1 Gb</dd>"));
+        let human = NumFormat { human_sizes: true, ..comma };
+        assert!(Summary::of(&rows, 0).text(human).contains(", 1.235,1 Gb;"));
+    }
+
+    #[test]
+    fn sort_by_seen_lists_new_studies_first() {
+        let mut rows = study_rows(&[("PRJEB1", "2026-01-03"), ("PRJEB2", "2026-01-02"), ("PRJEB3", "2026-01-01")]);
+        let known: BTreeSet<String> = ["PRJEB1".to_string(), "PRJEB3".to_string()].into();
+        for r in rows.iter_mut() { r.seen = Some(known.contains(&r.acc)); }
+        sort_rows(&mut rows, Column::Seen);
+        let order: Vec<&str> = rows.iter().map(|r| r.acc.as_str()).collect();
+        assert_eq!(order, ["PRJEB2", "PRJEB1", "PRJEB3"]);
+    }
+
+    #[test]
+    fn pager_session_collects_every_table() {
+        let df = DataFrame::new(vec![Series::new("study".into(), ["PRJEB1"])]).unwrap();
+        *PAGED.lock().unwrap() = Some(String::new());
+        print_df(&df, &PrintOptions::default()).unwrap();
+        outln!("Top {} species by gigabases:", 1);
+        print_df(&df, &PrintOptions::default()).unwrap();
+        let text = PAGED.lock().unwrap().take().unwrap();
+        assert_eq!(text.matches("PRJEB1").count(), 2);
+        assert!(text.contains("Top 1 species by gigabases:\n"));
+    }
+
+    #[test]
+    fn quiet_turns_off_the_pager() {
+        assert!(Cli::try_parse_from(["herring", "list", "-q", "-v"]).is_err());
+        let report = |extra: &[&str]| match Cli::try_parse_from(["herring", "list"].iter().chain(extra)).unwrap().command {
+            Commands::List(args) => args.report,
+            _ => panic!("not list"),
+        };
+        assert!(report(&["--pager"]).paging_on(true));
+        assert!(!report(&["--pager"]).paging_on(false));
+        assert!(!report(&["--pager", "--quiet"]).paging_on(true));
+        assert!(!report(&[]).paging_on(true));
+    }
+
+    #[test]
+    fn html_number_cells_all_carry_a_sort_value() {
+        let mut rows = study_rows(&[("PRJEB1", "2026-01-02"), ("PRJEB2", "2026-01-01")]);
+        rows[0].analyses = Some(3);
+        let path = temp_path("numbers.html");
+        let columns = [Column::StudyAccession, Column::Biosamples, Column::Analyses, Column::Reads];
+        write_html(&rows, &columns, path.clone(), SpeciesLink::None, None, Column::ReleaseDate, &html_meta(NumFormat::default())).unwrap();
+        let html = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert!(html.contains("<td data-v=\"3\">3</td>"));
+        assert!(html.contains("<td data-v=\"\"></td>"), "missing analyses must sort as blank");
+        assert_eq!(html.matches("<td data-v=").count(), rows.len() * 3);
+    }
+
+    #[test]
+    fn strict_ont_drops_only_known_non_ont_models() {
+        let models = [Some("MinION"), Some("Illumina NovaSeq 6000"), Some("ONT sequencer"), Some("unspecified"), Some(""), None];
+        let runs: Vec<RunRecord> = models.iter().map(|m| run(serde_json::json!({ "study_accession": "PRJEB1", "instrument_model": m }))).collect();
+        let kept = strict_ont(runs, StrictOnt::Drop);
+        let models: Vec<Option<&str>> = kept.iter().map(|r| r.instrument_model.as_deref()).collect();
+        assert_eq!(models, [Some("MinION"), Some("unspecified"), Some(""), None]);
+    }
+
+    #[test]
+    fn watch_rejects_bad_windows_and_intervals() {
+        let parse = |extra: &[&str]| Cli::try_parse_from(["herring", "watch"].iter().chain(extra)).map(|c| match c.command {
+            Commands::Watch(args) => args,
+            _ => panic!("not watch"),
+        });
+        assert!(parse(&["--interval-mins", "999999999999999999"]).is_err());
+        assert!(parse(&["--interval-mins", "0"]).is_err());
+        let today = ymd("2026-06-15");
+        assert_eq!(watch_since(&parse(&["--weeks", "2"]).unwrap(), today).unwrap(), ymd("2026-06-01"));
+        for (weeks, msg) in [("0", "--weeks must be at least 1"), ("-3", "--weeks must be at least 1"), ("1000", "is before --min-date 2010-01-01"),
+                             ("9999999999999999", "reaches before the earliest representable date")] {
+            let err = watch_since(&parse(&[&format!("--weeks={}", weeks)]).unwrap(), today).unwrap_err().to_string();
+            assert!(err.contains(msg), "{}: {}", weeks, err);
+        }
+        assert!(watch_since(&parse(&["--weeks", "1000", "--force"]).unwrap(), today).is_ok());
+    }
+
+    #[test]
+    fn minimal_preset_rejects_filters_on_unfetched_fields() {
+        let resolve = |extra: &[&str]| {
+            let Commands::List(mut args) = Cli::try_parse_from(["herring", "list", "--fields-preset", "minimal"].iter().chain(extra)).unwrap().command else { panic!("not list") };
+            args.report.resolve().map_err(|e| e.to_string())
+        };
+        assert!(resolve(&[]).is_ok());
+        assert!(resolve(&["--sort-by", "gigabases"]).is_ok());
+        for (flag, field) in [(&["--collected-from", "2020-01-01"][..], "collection_date"), (&["--center", "Sanger"], "center_name"),
+                              (&["--species", "Homo sapiens"], "scientific_name"), (&["--drop-unknown-platform"], "instrument_model"),
+                              (&["--platform", "MinION"], "instrument_model")] {
+            let err = resolve(flag).unwrap_err();
+            assert!(err.contains(&format!("needs {}, which --fields-preset minimal does not fetch", field)), "{:?}: {}", flag, err);
+        }
+    }
+
+    #[test]
+    fn full_preset_renders_collection_dates_and_tax_ids() {
+        let runs: Vec<RunRecord> = [("2021", "9606"), ("2020-05-03", "9606"), ("missing", "")].iter().map(|(date, tax)| run(serde_json::json!({
+            "study_accession": "PRJEB1", "scientific_name": "Homo sapiens", "collection_date": date, "tax_id": tax,
+        }))).collect();
+        let rows = aggregate(&runs, &AggregateOptions::default()).0;
+        let cols = table_columns(ColumnFlags { preset: FieldsPreset::Full, ..ColumnFlags::default() }, false);
+        assert!(cols.contains(&Column::Collected) && cols.contains(&Column::TaxIds) && cols.contains(&Column::Size));
+        assert_eq!(Column::Collected.text(&rows[0]), "2020-05-03 .. 2021-12-31");
+        assert_eq!(Column::TaxIds.text(&rows[0]), "9606");
+        assert!(!table_columns(ColumnFlags::default(), false).contains(&Column::Collected));
+    }
+
+    #[test]
+    fn state_mark_is_guarded_and_written_atomically() {
+        let (today, floor) = (ymd("2026-06-15"), Some(ymd("2020-01-01")));
+        assert!(check_state_mark(ymd("2026-06-01"), today, true, floor).is_ok());
+        let err = check_state_mark(ymd("2001-01-01"), today, false, floor).unwrap_err();
+        assert!(format!("{:#}", err).contains("computed window start 2001-01-01 is before --min-date"), "{:#}", err);
+        assert!(check_state_mark(ymd("2001-01-01"), today, false, None).is_ok());
+        assert!(check_state_mark(ymd("2027-01-01"), today, false, floor).is_ok());
+        let err = check_state_mark(ymd("2027-01-01"), today, true, floor).unwrap_err().to_string();
+        assert!(err.contains("high-water mark 2027-01-01 is in the future"), "{}", err);
+
+        let path = temp_path("state.json");
+        write_state(&path, ymd("2026-06-01")).unwrap();
+        write_state(&path, ymd("2026-06-02")).unwrap();
+        assert_eq!(read_state(&path).unwrap(), Some(ymd("2026-06-02")));
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        assert!(!PathBuf::from(tmp).exists());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn week_rows_bucket_by_iso_week() {
+        let mut rows = study_rows(&[("PRJEB1", "2021-01-04"), ("PRJEB2", "2020-12-31"), ("PRJEB3", "2021-01-01"), ("PRJEB4", "")]);
+        rows[1].bases = u128::MAX;
+        let weeks = week_rows(&rows);
+        let keys: Vec<(&str, u32)> = weeks.iter().map(|g| (g.key.as_str(), g.studies)).collect();
+        // 2021-01-01 is a Friday in ISO week 53 of 2020.
+        assert_eq!(keys, [("2020-W53", 2), ("2021-W01", 1), ("(unknown)", 1)]);
+        assert_eq!(weeks[0].bases, u128::MAX);
+        assert_eq!((weeks[1].runs, weeks[1].bases), (1, 1_000_000_000));
+    }
+
+    #[test]
+    fn stats_tables_share_the_report_styling() {
+        let Commands::List(args) = Cli::try_parse_from(["herring", "list", "--stats", "--pretty", "--color", "always", "--max-col-width", "12"]).unwrap().command else { panic!("not list") };
+        let opts = args.report.print_options();
+        assert_eq!((opts.max_col_width, opts.color, opts.pretty), (Some(12), true, true));
+        let plain = PrintOptions::default();
+        assert_eq!((plain.max_col_width, plain.color, plain.pretty), (None, false, false));
+    }
+}
@@ -0,0 +1,746 @@
+//! Per-study aggregation of ENA run rows, shared by the `herring` binary and library users.
+//!
+//! [`aggregate`] folds [`RunRecord`]s into one [`Row`] per study, applying the
+//! species, center, platform, strategy and gigabases filters on the way; [`group`]
+//! re-folds the surviving studies' runs by a [`GroupBy`] key.
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use log::{info, warn};
+use std::{borrow::Cow, collections::{BTreeMap, BTreeSet}};
+
+use crate::ena::{map_platform, map_strategy, parse_collection_date, RunRecord};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+/// How `--dedup-bases-by` counts base_count.
+pub enum BaseDedup {
+    /// Sum every run.
+    Run,
+    /// Count each sample once, using its largest run base_count.
+    Sample,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Aggregation key for `--group-by`.
+pub enum GroupBy {
+    /// One group per study (the plain study table).
+    #[default]
+    Study,
+    /// Normalized platform label (MinION, GridION, ...).
+    Platform,
+    /// Species name, as listed in the species column.
+    Species,
+    /// Run center_name.
+    Center,
+}
+
+impl GroupBy {
+    /// Column header for the key.
+    pub fn name(self) -> &'static str {
+        match self {
+            GroupBy::Study => "study_accession",
+            GroupBy::Platform => "platform",
+            GroupBy::Species => "species",
+            GroupBy::Center => "center",
+        }
+    }
+
+    /// Group a run belongs to; blank species/center land in `(unknown)`, or in the
+    /// `--unknown-species-label` for species when one is set. Species keys are reduced
+    /// with [`species_name`] under `--normalize-species`; the include/exclude lists are unused.
+    fn key(self, r: &RunRecord, species: SpeciesFilter) -> String {
+        let or_unknown = |v: Option<&str>| v.map(str::trim).filter(|v| !v.is_empty()).unwrap_or("(unknown)").to_string();
+        match self {
+            GroupBy::Study => r.study_accession.clone(),
+            GroupBy::Platform => map_platform(r.instrument_model.as_deref()).to_string(),
+            GroupBy::Species => match r.scientific_name.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+                Some(name) => species_name(name, species.normalize),
+                None if !species.unknown_label.is_empty() => species.unknown_label.to_string(),
+                None => "(unknown)".to_string(),
+            },
+            GroupBy::Center => or_unknown(r.center_name.as_deref()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// Which `first_public` of a study's runs becomes its [`Row::release`] (and so its place in the table).
+pub enum ReleasePolicy {
+    /// The earliest run's: when the study first had ONT data out.
+    #[default]
+    Earliest,
+    /// The latest run's: when the study last grew.
+    Latest,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// How `--species-order` lists a study's species.
+pub enum SpeciesOrder {
+    /// Alphabetically.
+    #[default]
+    Alpha,
+    /// Most runs first, ties alphabetically.
+    Count,
+}
+
+#[derive(Clone)]
+/// One study, folded from its runs by [`aggregate`]; the binary builds its tables and exports from these.
+pub struct Row {
+    /// Study accession.
+    pub acc: String,
+    /// The study's release date under [`ReleasePolicy`] (default: earliest `first_public`).
+    pub release: String,
+    /// Latest `first_public` among the study's runs.
+    pub release_last: String,
+    /// Distinct [`map_platform`] labels, `, `-joined.
+    pub platform: String,
+    /// Distinct raw `instrument_model` strings, `, `-joined.
+    pub raw_models: String,
+    /// Distinct [`map_strategy`] buckets, `, `-joined.
+    pub seq_type: String,
+    /// Distinct `library_source` values, `, `-joined.
+    pub lib_source: String,
+    /// Distinct `library_selection` values, `, `-joined.
+    pub lib_selection: String,
+    /// Up to 5 distinct species names, `, `-joined.
+    pub species: String,
+    /// Every distinct species name behind `species`, in [`SpeciesOrder`].
+    pub species_names: Vec<String>,
+    /// Distinct run center names.
+    pub centers: String,
+    /// `tax_id` per species name, where ENA reported one.
+    pub tax_ids: BTreeMap<String, String>,
+    /// Earliest and latest day covered by the runs' [`parse_collection_date`] ranges;
+    /// `None` when no run has an interpretable `collection_date`.
+    pub collected: Option<(NaiveDate, NaiveDate)>,
+    /// Distinct sample accessions.
+    pub biosamples: u32,
+    /// Rounded to [`NumFormat::precision`] decimals; `None` when no run reported a parseable
+    /// base count, as opposed to a real zero.
+    pub gigabases_num: Option<f64>,
+    /// Empty when `gigabases_num` is `None`.
+    pub gigabases_str: String,
+    /// Total bases under the chosen [`BaseDedup`].
+    pub bases: u128,
+    /// Summed `read_count` (0 unless the `full` preset fetched it).
+    pub reads: u128,
+    /// Runs folded into the study.
+    pub run_count: u32,
+    /// Summed `fastq_bytes`.
+    pub fastq_bytes: u128,
+    /// First non-empty `study_title`.
+    pub title: String,
+    /// Every distinct sample title, sorted; the column shows the first 5.
+    pub sample_titles: Vec<String>,
+    /// Every distinct raw `scientific_name`, sorted (before `--normalize-species`).
+    pub scientific_names: Vec<String>,
+    /// Distinct `fastq_ftp` paths.
+    pub fastq: Vec<String>,
+    /// Whether `--seen-db` already listed the study; `None` without it.
+    pub seen: Option<bool>,
+    /// ENA `analysis` records for the study under `--include-analyses`; `None` without it.
+    pub analyses: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// `--platform`/`--exclude-platform`, as canonical [`PLATFORMS`](crate::ena::PLATFORMS) labels.
+pub struct PlatformFilter<'a> {
+    /// Keep studies with a run on any of these.
+    pub include: &'a [String],
+    /// Drop studies with every run on these.
+    pub exclude: &'a [String],
+    /// Drop studies with fewer distinct platform labels than this (0 and 1 keep everything).
+    pub min_count: usize,
+}
+
+impl PlatformFilter<'_> {
+    /// Include first (any run on an included platform), then exclude (drop only when
+    /// every run is on an excluded platform, so mixed studies survive).
+    pub fn keeps(&self, plats: &BTreeSet<String>) -> bool {
+        (self.include.is_empty() || plats.iter().any(|p| self.include.contains(p)))
+            && (self.exclude.is_empty() || plats.iter().any(|p| !self.exclude.contains(p)))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// `--strategy`/`--exclude-strategy`, as lowercase [`map_strategy`] buckets (the sequencing_type column).
+pub struct StrategyFilter<'a> {
+    /// Keep studies with a run in any of these.
+    pub include: &'a [String],
+    /// Drop studies with any run in these.
+    pub exclude: &'a [String],
+}
+
+impl StrategyFilter<'_> {
+    /// Include first (any run in an included bucket), then exclude (drop when any run is in an
+    /// excluded bucket, so mixed studies go too). Runs without a library_strategy match neither.
+    pub fn keeps(&self, types: &BTreeSet<String>) -> bool {
+        (self.include.is_empty() || types.iter().any(|t| self.include.contains(t)))
+            && !types.iter().any(|t| self.exclude.contains(t))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// `--species`/`--exclude-species` and `--normalize-species`.
+pub struct SpeciesFilter<'a> {
+    /// Keep studies with a raw name containing any of these (case-insensitive).
+    pub include: &'a [String],
+    /// Drop studies with a raw name containing any of these.
+    pub exclude: &'a [String],
+    /// Reduce names with [`species_name`] before listing them.
+    pub normalize: bool,
+    /// Listed (as is) for runs without a scientific_name; nothing is listed when empty.
+    pub unknown_label: &'a str,
+    /// Order of the listed names, and so which five the column keeps.
+    pub order: SpeciesOrder,
+}
+
+/// Species label for display and grouping: the raw name (see [`strip_controls`]), or
+/// its first two whitespace-separated words (genus + species) when `normalize` is set.
+pub fn species_name(raw: &str, normalize: bool) -> String {
+    let raw = strip_controls(raw);
+    if !normalize { return raw.into_owned() }
+    raw.split_whitespace().take(2).collect::<Vec<_>>().join(" ")
+}
+
+/// `s` without C0 control characters other than tab: stray `\x01`s or `\x1b` escapes in
+/// ENA free text would otherwise garble the terminal table and end up in CSV and HTML.
+/// Line breaks (`\n`, `\r`, `\r\n`) become one space each, so a row stays on one line.
+pub fn strip_controls(s: &str) -> Cow<'_, str> {
+    let stray = |c: char| c < ' ' && c != '\t';
+    if !s.contains(stray) { return Cow::Borrowed(s) }
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\r' | '\n' => out.push(' '),
+            c if stray(c) => {}
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Include/exclude test over a study's full (untruncated) set of species or center names.
+///
+/// Include first (any name contains any `include` substring, when given), then
+/// exclude (no name contains any `exclude` substring). Case-insensitive.
+fn names_match(names: &BTreeSet<String>, include: &[String], exclude: &[String]) -> bool {
+    let names: Vec<String> = names.iter().map(|n| n.to_lowercase()).collect();
+    let hit = |pat: &String| { let pat = pat.to_lowercase(); names.iter().any(|n| n.contains(&pat)) };
+    (include.is_empty() || include.iter().any(hit)) && !exclude.iter().any(hit)
+}
+
+#[derive(Default)]
+/// Base counts tallied both per run and per sample, resolved by [`BaseDedup`].
+///
+/// Runs without a sample accession always count in full.
+pub struct BaseTally {
+    /// Whether any base count was added at all.
+    pub seen: bool,
+    per_run: u128,
+    per_sample: BTreeMap<String, u128>,
+    unsampled: u128,
+}
+
+impl BaseTally {
+    /// Add one run's base count, under its sample when it has one.
+    pub fn add(&mut self, sample: Option<&str>, v: u128) {
+        self.seen = true;
+        self.per_run = self.per_run.saturating_add(v);
+        match sample.filter(|s| !s.is_empty()) {
+            Some(s) => { let m = self.per_sample.entry(s.to_string()).or_default(); *m = (*m).max(v); }
+            None => self.unsampled = self.unsampled.saturating_add(v),
+        }
+    }
+
+    /// Total bases, counting runs or samples per `by`.
+    pub fn total(&self, by: BaseDedup) -> u128 {
+        match by {
+            BaseDedup::Run => self.per_run,
+            BaseDedup::Sample => self.per_sample.values().fold(self.unsampled, |acc, v| acc.saturating_add(*v)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// How gigabases are rounded and rendered as text (`--gigabases-precision`, `--human-sizes`,
+/// `--decimal-comma`); JSON keeps the (rounded) number.
+pub struct NumFormat {
+    /// Grouped, unit-scaled text (`1,234.5 Gb`).
+    pub human_sizes: bool,
+    /// Comma decimals (`1234,5`).
+    pub decimal_comma: bool,
+    /// Decimal places for gigabases (default 1); `--human-sizes` text keeps one.
+    pub precision: usize,
+}
+
+impl Default for NumFormat {
+    fn default() -> Self {
+        NumFormat { human_sizes: false, decimal_comma: false, precision: 1 }
+    }
+}
+
+impl NumFormat {
+    /// Stdout/CSV/HTML text for a study's gigabases; empty when none were reported.
+    pub fn gigabases(self, gigabases: Option<f64>, bases: u128) -> String {
+        match gigabases {
+            None => String::new(),
+            Some(_) if self.human_sizes => self.localize(format_bases(bases)),
+            Some(gb) => self.plain(gb),
+        }
+    }
+
+    /// `gb` with `precision` decimals, localized (no units or grouping).
+    pub fn plain(self, gb: f64) -> String {
+        // Formatted from the rounded value so text and JSON agree on halves (`{:.0}` of 0.5 is "0").
+        self.localize(format!("{:.*}", self.precision, self.round(gb)))
+    }
+
+    /// Round `gb` to `precision` decimals, for the numeric (JSON) value.
+    pub fn round(self, gb: f64) -> f64 {
+        let scale = 10f64.powi(self.precision as i32);
+        (gb * scale).round() / scale
+    }
+
+    /// Swap `.` and `,` under `--decimal-comma` (`1,234.5 Gb` → `1.234,5 Gb`).
+    pub fn localize(self, s: String) -> String {
+        if !self.decimal_comma { return s }
+        s.chars().map(|c| match c { '.' => ',', ',' => '.', c => c }).collect()
+    }
+
+    /// CSV field delimiter: `;` when decimals use a comma, so cells stay unquoted and parseable.
+    pub fn csv_delimiter(self) -> u8 {
+        if self.decimal_comma { b';' } else { b',' }
+    }
+}
+
+#[derive(Default)]
+/// Per-study accumulator used while folding run rows.
+struct Agg {
+    plats: BTreeSet<String>,
+    models: BTreeSet<String>,
+    types: BTreeSet<String>,
+    sources: BTreeSet<String>,
+    selections: BTreeSet<String>,
+    /// Runs per listed species name.
+    species: BTreeMap<String, u32>,
+    raw_species: BTreeSet<String>,
+    tax_ids: BTreeMap<String, String>,
+    collected: Option<(NaiveDate, NaiveDate)>,
+    centers: BTreeSet<String>,
+    samples: BTreeSet<String>,
+    sample_titles: BTreeSet<String>,
+    fastq: BTreeSet<String>,
+    bases: BaseTally,
+    reads: u128,
+    runs: u32,
+    bytes: u128,
+    title: String,
+    release: String,
+    release_last: String,
+}
+
+/// Everything [`aggregate`] needs besides the runs; the default keeps every study.
+#[derive(Clone, Copy, Debug)]
+pub struct AggregateOptions<'a> {
+    /// Species include/exclude and normalization.
+    pub species: SpeciesFilter<'a>,
+    /// Keep studies with a run center_name containing any of these (case-insensitive).
+    pub centers: &'a [String],
+    /// Platform include/exclude.
+    pub platforms: PlatformFilter<'a>,
+    /// Sequencing-type (strategy bucket) include/exclude.
+    pub strategies: StrategyFilter<'a>,
+    /// How base counts are summed.
+    pub dedup_bases: BaseDedup,
+    /// How gigabases are rendered in [`Row::gigabases_str`].
+    pub num: NumFormat,
+    /// Species names kept in [`Row::species`] (default 5); the full list stays in [`Row::scientific_names`].
+    pub max_species: usize,
+    /// Drop studies with fewer gigabases than this (unrounded, under `dedup_bases`);
+    /// studies without any base count go too.
+    pub min_gigabases: Option<f64>,
+    /// Which run's `first_public` dates the study.
+    pub release: ReleasePolicy,
+    /// Key [`group`] folds runs by; [`aggregate`] itself always yields study rows.
+    pub group_by: GroupBy,
+}
+
+impl Default for AggregateOptions<'_> {
+    fn default() -> Self {
+        AggregateOptions {
+            species: SpeciesFilter::default(),
+            centers: &[],
+            platforms: PlatformFilter::default(),
+            strategies: StrategyFilter::default(),
+            dedup_bases: BaseDedup::Run,
+            num: NumFormat::default(),
+            max_species: 5,
+            min_gigabases: None,
+            release: ReleasePolicy::Earliest,
+            group_by: GroupBy::Study,
+        }
+    }
+}
+
+/// Fold run rows into one [`Row`] per study, newest first (ties by accession).
+///
+/// Species filters are applied against each study's full set of raw names, before
+/// normalizing and truncating the displayed list; `centers` keeps studies with any matching run
+/// center. Also returns the number of unparseable base counts. No I/O beyond logging.
+pub fn aggregate(runs: &[RunRecord], opts: &AggregateOptions) -> (Vec<Row>, usize) {
+    let AggregateOptions { species, centers, platforms, strategies, dedup_bases, num, max_species, min_gigabases, release, group_by: _ } = *opts;
+    let mut by_study: BTreeMap<String, Agg> = BTreeMap::new();
+    let mut bad_base_counts: usize = 0;
+
+    for r in runs {
+        let a = by_study.entry(r.study_accession.clone()).or_default();
+        a.runs += 1;
+        a.plats.insert(map_platform(r.instrument_model.as_deref()).to_string());
+        if let Some(m) = r.instrument_model.as_deref() { if !m.is_empty() { a.models.insert(m.to_string()); } }
+        if let Some(strat) = r.library_strategy.as_deref() { a.types.insert(map_strategy(strat)); }
+        if let Some(src) = r.library_source.as_deref() { if !src.is_empty() { a.sources.insert(src.to_string()); } }
+        if let Some(sel) = r.library_selection.as_deref() { if !sel.is_empty() { a.selections.insert(sel.to_string()); } }
+        match r.scientific_name.as_deref().map(strip_controls).filter(|sp| !sp.is_empty()) {
+            Some(sp) => {
+                let name = species_name(&sp, species.normalize);
+                // A strain's tax_id would mislabel the collapsed name, so only exact names keep theirs.
+                if let Some(id) = r.tax_id.as_deref().filter(|id| !id.is_empty() && name == *sp) { a.tax_ids.entry(name.clone()).or_insert_with(|| id.to_string()); }
+                *a.species.entry(name).or_default() += 1;
+                a.raw_species.insert(sp.into_owned());
+            }
+            None if !species.unknown_label.is_empty() => { *a.species.entry(species.unknown_label.to_string()).or_default() += 1; }
+            None => {}
+        }
+        if let Some(c) = r.center_name.as_deref() { if !c.is_empty() { a.centers.insert(c.to_string()); } }
+        if let Some((lo, hi)) = r.collection_date.as_deref().and_then(parse_collection_date) {
+            a.collected = Some(a.collected.map_or((lo, hi), |(l, h)| (l.min(lo), h.max(hi))));
+        }
+        if let Some(fp) = r.first_public.as_deref() {
+            if a.release.is_empty() || fp < a.release.as_str() { a.release = fp.to_string(); }
+            if fp > a.release_last.as_str() { a.release_last = fp.to_string(); }
+        }
+        if let Some(t) = r.study_title.as_deref().map(strip_controls) { if !t.is_empty() && a.title.is_empty() { a.title = t.into_owned(); } }
+        if let Some(samp) = r.sample_accession.as_deref() { if !samp.is_empty() { a.samples.insert(samp.to_string()); } }
+        if let Some(st) = r.sample_title.as_deref().map(strip_controls) { let st = st.trim(); if !st.is_empty() { a.sample_titles.insert(st.to_string()); } }
+        if let Some(fq) = r.fastq_ftp.as_deref() { a.fastq.extend(fq.split(';').map(str::trim).filter(|f| !f.is_empty()).map(str::to_string)); }
+        if let Some(bc) = r.base_count.as_deref().map(str::trim).filter(|bc| !bc.is_empty()) {
+            match bc.parse::<u128>() {
+                Ok(v) => a.bases.add(r.sample_accession.as_deref(), v),
+                Err(e) => {
+                    bad_base_counts += 1;
+                    warn!("run {}: ignoring unparseable base_count {:?}: {}", r.run_accession.as_deref().unwrap_or("?"), bc, e);
+                }
+            }
+        }
+        if let Some(v) = r.read_count.as_deref().and_then(|rc| rc.trim().parse::<u128>().ok()) { a.reads = a.reads.saturating_add(v); }
+        if let Some(fb) = r.fastq_bytes.as_deref() {
+            for part in fb.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+                if let Ok(v) = part.parse::<u64>() { a.bytes = a.bytes.saturating_add(v as u128); }
+            }
+        }
+    }
+
+    if !species.include.is_empty() || !species.exclude.is_empty() {
+        let before = by_study.len();
+        by_study.retain(|_, a| names_match(&a.raw_species, species.include, species.exclude));
+        info!("species filters kept {} of {} studies", by_study.len(), before);
+    }
+    if !centers.is_empty() {
+        let before = by_study.len();
+        by_study.retain(|_, a| names_match(&a.centers, centers, &[]));
+        info!("center filter kept {} of {} studies", by_study.len(), before);
+    }
+    if !platforms.include.is_empty() || !platforms.exclude.is_empty() {
+        let before = by_study.len();
+        by_study.retain(|_, a| platforms.keeps(&a.plats));
+        info!("platform filters kept {} of {} studies", by_study.len(), before);
+    }
+    if platforms.min_count > 1 {
+        let before = by_study.len();
+        by_study.retain(|_, a| a.plats.len() >= platforms.min_count);
+        info!("--min-platforms {} removed {} studies", platforms.min_count, before - by_study.len());
+    }
+    if !strategies.include.is_empty() || !strategies.exclude.is_empty() {
+        let before = by_study.len();
+        by_study.retain(|_, a| strategies.keeps(&a.types));
+        info!("strategy filters kept {} of {} studies", by_study.len(), before);
+    }
+    if let Some(min) = min_gigabases {
+        let before = by_study.len();
+        by_study.retain(|_, a| a.bases.seen && a.bases.total(dedup_bases) as f64 / 1e9_f64 >= min);
+        info!("minimum gigabases {} removed {} studies", min, before - by_study.len());
+    }
+
+    let mut rows: Vec<Row> = Vec::new();
+
+    for (acc, a) in by_study.into_iter() {
+        let plat = a.plats.into_iter().collect::<Vec<_>>().join(", ");
+        let raw_models = a.models.into_iter().collect::<Vec<_>>().join(", ");
+        let centers = a.centers.into_iter().collect::<Vec<_>>().join(", ");
+        let seqt = a.types.into_iter().collect::<Vec<_>>().join(", ");
+        let lib_source = a.sources.into_iter().collect::<Vec<_>>().join(", ");
+        let lib_selection = a.selections.into_iter().collect::<Vec<_>>().join(", ");
+        let mut by_name: Vec<(String, u32)> = a.species.into_iter().collect();
+        // Stable sort over the alphabetical map order, so equal counts stay A-Z.
+        if species.order == SpeciesOrder::Count { by_name.sort_by_key(|(_, n)| std::cmp::Reverse(*n)); }
+        let species_names: Vec<String> = by_name.into_iter().map(|(name, _)| name).collect();
+        let sp = species_names.iter().take(max_species).cloned().collect::<Vec<_>>().join(", ");
+        let biosamples = a.samples.len() as u32;
+        let bases = a.bases.total(dedup_bases);
+        let gb = (bases as f64) / 1e9_f64;
+        let gigabases_num = a.bases.seen.then_some(num.round(gb));
+        let gigabases_str = num.gigabases(gigabases_num, bases);
+        let released = match release { ReleasePolicy::Earliest => a.release, ReleasePolicy::Latest => a.release_last.clone() };
+        rows.push(Row {
+            acc, release: released, release_last: a.release_last, platform: plat, raw_models, seq_type: seqt, lib_source, lib_selection,
+            species: sp, species_names, centers, tax_ids: a.tax_ids, collected: a.collected, biosamples, gigabases_num, gigabases_str, bases,
+            reads: a.reads, run_count: a.runs, fastq_bytes: a.bytes, title: a.title,
+            sample_titles: a.sample_titles.into_iter().collect(), scientific_names: a.raw_species.into_iter().collect(),
+            fastq: a.fastq.into_iter().collect(), seen: None, analyses: None,
+        });
+    }
+
+    // Newest first, ties broken by accession so identical inputs give identical output.
+    rows.sort_by(|a, b| b.release.cmp(&a.release).then_with(|| a.acc.cmp(&b.acc)));
+    (rows, bad_base_counts)
+}
+
+/// One [`group`] row: totals over the runs of the surviving studies that fall in `key`.
+#[derive(Clone, Debug)]
+pub struct GroupRow {
+    /// The group's [`GroupBy`] value (or ISO week for `--by-week`).
+    pub key: String,
+    /// Distinct studies.
+    pub studies: u32,
+    /// Distinct sample accessions.
+    pub biosamples: u32,
+    /// Runs.
+    pub runs: u32,
+    /// Total bases under the chosen [`BaseDedup`].
+    pub bases: u128,
+}
+
+/// Re-fold the runs of the studies in `rows` (i.e. after every study filter) by
+/// `opts.group_by`, largest base total first, ties by key. Only `group_by`,
+/// `dedup_bases` and the species naming of `opts` apply here.
+pub fn group(runs: &[RunRecord], rows: &[Row], opts: &AggregateOptions) -> Vec<GroupRow> {
+    #[derive(Default)]
+    struct Acc<'a> { studies: BTreeSet<&'a str>, samples: BTreeSet<&'a str>, runs: u32, bases: BaseTally }
+
+    let kept: BTreeSet<&str> = rows.iter().map(|r| r.acc.as_str()).collect();
+    let mut groups: BTreeMap<String, Acc> = BTreeMap::new();
+    for r in runs.iter().filter(|r| kept.contains(r.study_accession.as_str())) {
+        let g = groups.entry(opts.group_by.key(r, opts.species)).or_default();
+        g.studies.insert(&r.study_accession);
+        if let Some(samp) = r.sample_accession.as_deref() { if !samp.is_empty() { g.samples.insert(samp); } }
+        g.runs += 1;
+        // Unparseable values were already counted and warned about by `aggregate`.
+        if let Some(v) = r.base_count.as_deref().and_then(|bc| bc.trim().parse::<u128>().ok()) { g.bases.add(r.sample_accession.as_deref(), v); }
+    }
+    let mut out: Vec<GroupRow> = groups.into_iter().map(|(key, g)| GroupRow {
+        key, studies: g.studies.len() as u32, biosamples: g.samples.len() as u32, runs: g.runs, bases: g.bases.total(opts.dedup_bases),
+    }).collect();
+    out.sort_by(|a, b| b.bases.cmp(&a.bases).then_with(|| a.key.cmp(&b.key)));
+    out
+}
+
+/// Group the integer part of a non-negative decimal string with commas (`1234.5` → `1,234.5`).
+fn group_thousands(s: &str) -> String {
+    let (int, frac) = s.split_once('.').map_or((s, None), |(i, f)| (i, Some(f)));
+    let mut out = String::new();
+    for (i, ch) in int.chars().enumerate() {
+        if i > 0 && (int.len() - i) % 3 == 0 { out.push(','); }
+        out.push(ch);
+    }
+    if let Some(f) = frac { out.push('.'); out.push_str(f); }
+    out
+}
+
+/// Base count as grouped, unit-scaled text: `1,234.5 Gb`, `12.3 Tb`, `4.0 Pb`.
+///
+/// Values stay in Gb up to 9,999.9 Gb, then switch to Tb, and to Pb at 10,000 Tb.
+pub fn format_bases(bases: u128) -> String {
+    let mut v = (bases as f64) / 1e9_f64;
+    let mut unit = "Gb";
+    for next in ["Tb", "Pb"] {
+        if v < 10_000.0 { break; }
+        v /= 1000.0;
+        unit = next;
+    }
+    format!("{} {}", group_thousands(&format!("{:.1}", v)), unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A run from the JSON fields ENA would return; absent fields are `None`.
+    fn run(v: serde_json::Value) -> RunRecord {
+        serde_json::from_value(v).unwrap()
+    }
+
+    /// One MinION run per `(study, scientific_name)`.
+    fn species_runs(pairs: &[(&str, &str)]) -> Vec<RunRecord> {
+        pairs.iter().map(|(study, sp)| run(serde_json::json!({ "study_accession": study, "scientific_name": sp, "instrument_model": "MinION", "first_public": "2026-01-01" }))).collect()
+    }
+
+    fn accessions(rows: &[Row]) -> Vec<&str> {
+        rows.iter().map(|r| r.acc.as_str()).collect()
+    }
+
+    /// Three studies: PRJEB1 (MinION + GridION, two species, one sample, 4 Gb over two
+    /// runs), PRJEB2 (PromethION RNA-Seq, 0.5 Gb) and PRJEB3 (MinION amplicon, unnamed,
+    /// no base count).
+    fn fixture() -> Vec<RunRecord> {
+        let row = |run_acc: &str, study: &str, model: &str, strategy: &str, species: Option<&str>, center: &str, sample: &str, bases: &str, date: &str| run(serde_json::json!({
+            "run_accession": run_acc, "study_accession": study, "instrument_model": model, "library_strategy": strategy, "scientific_name": species,
+            "center_name": center, "sample_accession": sample, "base_count": bases, "first_public": date,
+        }));
+        vec![
+            row("ERR1", "PRJEB1", "MinION", "WGS", Some("Homo sapiens"), "Sanger", "S1", "3000000000", "2026-01-05"),
+            row("ERR2", "PRJEB1", "GridION", "WGS", Some("Mus musculus"), "Sanger", "S1", "1000000000", "2026-01-09"),
+            row("ERR3", "PRJEB2", "PromethION", "RNA-Seq", Some("Escherichia coli K-12"), "Broad", "S2", "500000000", "2026-01-07"),
+            row("ERR4", "PRJEB3", "MinION", "AMPLICON", None, "", "S3", "", "2026-01-06"),
+        ]
+    }
+
+    fn fixture_rows(opts: &AggregateOptions) -> Vec<Row> {
+        aggregate(&fixture(), opts).0
+    }
+
+    #[test]
+    fn default_options_keep_every_study() {
+        let rows = fixture_rows(&AggregateOptions::default());
+        assert_eq!(accessions(&rows), ["PRJEB2", "PRJEB3", "PRJEB1"]);
+        assert_eq!(rows[2].platform, "GridION, MinION");
+        assert_eq!(rows[2].gigabases_str, "4.0");
+        assert_eq!(rows[1].gigabases_num, None);
+    }
+
+    #[test]
+    fn center_and_strategy_options() {
+        let sanger = ["sang".to_string()];
+        assert_eq!(accessions(&fixture_rows(&AggregateOptions { centers: &sanger, ..Default::default() })), ["PRJEB1"]);
+        let rna = ["transcriptome".to_string()];
+        let opts = AggregateOptions { strategies: StrategyFilter { include: &rna, exclude: &[] }, ..Default::default() };
+        assert_eq!(accessions(&fixture_rows(&opts)), ["PRJEB2"]);
+        let genome = ["genome".to_string()];
+        let opts = AggregateOptions { strategies: StrategyFilter { include: &[], exclude: &genome }, ..Default::default() };
+        assert_eq!(accessions(&fixture_rows(&opts)), ["PRJEB2"]);
+    }
+
+    #[test]
+    fn min_platforms_option() {
+        let opts = AggregateOptions { platforms: PlatformFilter { min_count: 2, ..Default::default() }, ..Default::default() };
+        assert_eq!(accessions(&fixture_rows(&opts)), ["PRJEB1"]);
+    }
+
+    #[test]
+    fn dedup_and_number_format_options() {
+        let opts = AggregateOptions { dedup_bases: BaseDedup::Sample, num: NumFormat { human_sizes: false, decimal_comma: true, precision: 2 }, ..Default::default() };
+        let rows = fixture_rows(&opts);
+        // Both PRJEB1 runs share sample S1, so only the larger one counts.
+        assert_eq!(rows[2].bases, 3_000_000_000);
+        assert_eq!(rows[2].gigabases_str, "3,00");
+        assert_eq!(rows[0].gigabases_num, Some(0.5));
+    }
+
+    #[test]
+    fn species_options() {
+        let opts = AggregateOptions { max_species: 1, species: SpeciesFilter { normalize: true, unknown_label: "unnamed", ..Default::default() }, ..Default::default() };
+        let rows = fixture_rows(&opts);
+        assert_eq!(rows[0].species, "Escherichia coli");
+        assert_eq!(rows[0].scientific_names, ["Escherichia coli K-12"]);
+        assert_eq!(rows[1].species, "unnamed");
+        assert_eq!(rows[2].species, "Homo sapiens");
+        assert_eq!(rows[2].species_names, ["Homo sapiens", "Mus musculus"]);
+    }
+
+    #[test]
+    fn min_gigabases_option() {
+        let rows = fixture_rows(&AggregateOptions { min_gigabases: Some(0.5), ..Default::default() });
+        assert_eq!(accessions(&rows), ["PRJEB2", "PRJEB1"]);
+        let rows = fixture_rows(&AggregateOptions { min_gigabases: Some(3.5), dedup_bases: BaseDedup::Sample, ..Default::default() });
+        assert!(rows.is_empty());
+    }
+
+    #[test]
+    fn release_policy_option() {
+        let rows = fixture_rows(&AggregateOptions { release: ReleasePolicy::Latest, ..Default::default() });
+        assert_eq!(accessions(&rows), ["PRJEB1", "PRJEB2", "PRJEB3"]);
+        assert_eq!((rows[0].release.as_str(), rows[0].release_last.as_str()), ("2026-01-09", "2026-01-09"));
+        let rows = fixture_rows(&AggregateOptions::default());
+        assert_eq!((rows[2].release.as_str(), rows[2].release_last.as_str()), ("2026-01-05", "2026-01-09"));
+    }
+
+    #[test]
+    fn group_by_option() {
+        let runs = fixture();
+        let grouped = |opts: AggregateOptions| {
+            let (rows, _) = aggregate(&runs, &opts);
+            group(&runs, &rows, &opts).into_iter().map(|g| (g.key, g.studies, g.runs, g.bases)).collect::<Vec<_>>()
+        };
+        assert_eq!(grouped(AggregateOptions { group_by: GroupBy::Platform, ..Default::default() }), [
+            ("MinION".to_string(), 2, 2, 3_000_000_000),
+            ("GridION".to_string(), 1, 1, 1_000_000_000),
+            ("PromethION".to_string(), 1, 1, 500_000_000),
+        ]);
+        let by_center = grouped(AggregateOptions { group_by: GroupBy::Center, ..Default::default() });
+        assert_eq!(by_center.iter().map(|g| g.0.as_str()).collect::<Vec<_>>(), ["Sanger", "Broad", "(unknown)"]);
+        // Grouping sees only the studies that survived the filters.
+        let rna = ["transcriptome".to_string()];
+        let opts = AggregateOptions {
+            group_by: GroupBy::Species,
+            strategies: StrategyFilter { include: &rna, exclude: &[] },
+            species: SpeciesFilter { normalize: true, ..Default::default() },
+            ..Default::default()
+        };
+        assert_eq!(grouped(opts), [("Escherichia coli".to_string(), 1, 1, 500_000_000)]);
+    }
+
+    #[test]
+    fn control_characters_in_titles() {
+        assert!(matches!(strip_controls("Plain\ttitle"), Cow::Borrowed("Plain\ttitle")));
+        assert_eq!(strip_controls("Soil\x01 metagenome"), "Soil metagenome");
+        assert_eq!(strip_controls("\x1b[31mRed\x1b[0m title"), "[31mRed[0m title");
+        assert_eq!(strip_controls("Line one\nline two\r\nthree\rfour"), "Line one line two three four");
+        let title = |t: &str| run(serde_json::json!({ "study_accession": "PRJEB1", "study_title": t, "sample_title": t, "scientific_name": t }));
+        let (rows, _) = aggregate(&[title("A\x01B\nC\x1b")], &AggregateOptions::default());
+        assert_eq!((rows[0].title.as_str(), rows[0].species.as_str()), ("AB C", "AB C"));
+        assert_eq!(rows[0].sample_titles, ["AB C"]);
+    }
+
+    #[test]
+    fn platform_filters_on_a_mixed_study() {
+        let model = |study: &str, m: &str| run(serde_json::json!({ "study_accession": study, "instrument_model": m, "first_public": "2026-01-01" }));
+        let runs = [model("PRJEB1", "MinION"), model("PRJEB1", "GridION"), model("PRJEB2", "GridION"), model("PRJEB3", "PromethION")];
+        let keep = |include: &[String], exclude: &[String]| {
+            let opts = AggregateOptions { platforms: PlatformFilter { include, exclude, min_count: 0 }, ..Default::default() };
+            accessions(&aggregate(&runs, &opts).0).into_iter().map(str::to_string).collect::<Vec<_>>()
+        };
+        let (minion, gridion) = (["MinION".to_string()], ["GridION".to_string()]);
+        assert_eq!(keep(&minion, &[]), ["PRJEB1"]);
+        assert_eq!(keep(&gridion, &[]), ["PRJEB1", "PRJEB2"]);
+        // The mixed study still has a run off the excluded platform, so it stays.
+        assert_eq!(keep(&[], &minion), ["PRJEB1", "PRJEB2", "PRJEB3"]);
+        assert_eq!(keep(&[], &gridion), ["PRJEB1", "PRJEB3"]);
+        assert_eq!(keep(&gridion, &minion), ["PRJEB1", "PRJEB2"]);
+    }
+
+    #[test]
+    fn species_include_and_exclude_together() {
+        let runs = species_runs(&[("PRJEB1", "Homo sapiens"), ("PRJEB1", "Mus musculus"), ("PRJEB2", "Homo sapiens"), ("PRJEB3", "Escherichia coli")]);
+        let (include, exclude) = (["homo".to_string()], ["MUS".to_string()]);
+        let opts = AggregateOptions { species: SpeciesFilter { include: &include, exclude: &exclude, ..Default::default() }, ..Default::default() };
+        // The mixed study has an included name, but also an excluded one.
+        assert_eq!(accessions(&aggregate(&runs, &opts).0), ["PRJEB2"]);
+        let exclude = ["coli".to_string()];
+        let opts = AggregateOptions { species: SpeciesFilter { include: &include, exclude: &exclude, ..Default::default() }, ..Default::default() };
+        let (rows, _) = aggregate(&runs, &opts);
+        assert_eq!(accessions(&rows), ["PRJEB1", "PRJEB2"]);
+        assert_eq!(rows[0].species, "Homo sapiens, Mus musculus");
+    }
+}